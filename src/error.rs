@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use crate::{COMPARISONS, OPERATIONS, UNARY_OPERATIONS};
+
+/// errors returned by spreadsheet parsing and operations
+#[derive(Debug)]
+pub enum SheetError {
+    /// no header matched the pattern
+    NoColumnMatch(String),
+    /// more than one header matched a pattern that must resolve to a single column
+    AmbiguousColumn { pattern:String, matches:Vec<String> },
+    /// a data row has a different number of fields than there are columns. `line` is 1-based.
+    RowLengthMismatch { line:usize, expected:usize, got:usize },
+    /// the name of an operation, unary operation or comparison that isn't supported
+    UnknownOperation(String),
+    /// a line that could not be split into fields, e.g. because of an unterminated quote. `line` is 1-based.
+    MalformedLine { line:usize, message:String },
+    /// a cell that could not be parsed as a number where one is required. `row` is the 0-based data row.
+    InvalidNumber { row:usize, column:String, value:String },
+    /// an arithmetic expression that could not be parsed
+    InvalidExpression(String),
+    Regex(regex::Error),
+    Shape(ndarray::ShapeError),
+    Io(io::Error),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    #[cfg(feature = "parquet")]
+    Arrow(arrow_schema::ArrowError),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(feature = "xlsx")]
+    Xlsx(calamine::XlsxError),
+    /// any other invalid input or argument
+    Invalid(String),
+}
+
+impl Display for SheetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SheetError::NoColumnMatch(pattern) => write!(f,"no matches found for '{}'",pattern),
+            SheetError::AmbiguousColumn { pattern, matches } => {
+                write!(f,"too many matches found for pattern '{}': {}. Consider narrowing your search pattern",pattern,matches.join(", "))
+            }
+            SheetError::RowLengthMismatch { line, expected, got } => {
+                write!(f,"issue with reading row at line {}! Expected {} element(s), found {}",line,expected,got)
+            }
+            SheetError::UnknownOperation(operation) => {
+                write!(f,"unknown operation {}. Supported operations are: {}, unary operations: {}, comparisons: {}",
+                    operation,OPERATIONS.join(" "),UNARY_OPERATIONS.join(" "),COMPARISONS.join(" "))
+            }
+            SheetError::MalformedLine { line, message } => write!(f,"issue with reading line {}! {}",line,message),
+            SheetError::InvalidNumber { row, column, value } => {
+                write!(f,"could not parse '{}' as a number at row {}, column '{}'",value,row,column)
+            }
+            SheetError::InvalidExpression(message) => write!(f,"{}",message),
+            SheetError::Regex(e) => write!(f,"{}",e),
+            SheetError::Shape(e) => write!(f,"{}",e),
+            SheetError::Io(e) => write!(f,"{}",e),
+            #[cfg(feature = "json")]
+            SheetError::Json(e) => write!(f,"{}",e),
+            #[cfg(feature = "parquet")]
+            SheetError::Arrow(e) => write!(f,"{}",e),
+            #[cfg(feature = "parquet")]
+            SheetError::Parquet(e) => write!(f,"{}",e),
+            #[cfg(feature = "xlsx")]
+            SheetError::Xlsx(e) => write!(f,"{}",e),
+            SheetError::Invalid(message) => write!(f,"{}",message),
+        }
+    }
+}
+
+impl Error for SheetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SheetError::Regex(e) => Some(e),
+            SheetError::Shape(e) => Some(e),
+            SheetError::Io(e) => Some(e),
+            #[cfg(feature = "json")]
+            SheetError::Json(e) => Some(e),
+            #[cfg(feature = "parquet")]
+            SheetError::Arrow(e) => Some(e),
+            #[cfg(feature = "parquet")]
+            SheetError::Parquet(e) => Some(e),
+            #[cfg(feature = "xlsx")]
+            SheetError::Xlsx(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for SheetError {
+    fn from(message:String) -> Self {
+        SheetError::Invalid(message)
+    }
+}
+
+impl From<&str> for SheetError {
+    fn from(message:&str) -> Self {
+        SheetError::Invalid(message.to_string())
+    }
+}
+
+impl From<regex::Error> for SheetError {
+    fn from(e:regex::Error) -> Self {
+        SheetError::Regex(e)
+    }
+}
+
+impl From<ndarray::ShapeError> for SheetError {
+    fn from(e:ndarray::ShapeError) -> Self {
+        SheetError::Shape(e)
+    }
+}
+
+impl From<io::Error> for SheetError {
+    fn from(e:io::Error) -> Self {
+        SheetError::Io(e)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for SheetError {
+    fn from(e:serde_json::Error) -> Self {
+        SheetError::Json(e)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<arrow_schema::ArrowError> for SheetError {
+    fn from(e:arrow_schema::ArrowError) -> Self {
+        SheetError::Arrow(e)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for SheetError {
+    fn from(e:parquet::errors::ParquetError) -> Self {
+        SheetError::Parquet(e)
+    }
+}
+
+#[cfg(feature = "xlsx")]
+impl From<calamine::XlsxError> for SheetError {
+    fn from(e:calamine::XlsxError) -> Self {
+        SheetError::Xlsx(e)
+    }
+}
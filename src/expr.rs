@@ -0,0 +1,201 @@
+use crate::SheetError;
+
+/// a parsed arithmetic expression over columns and numeric literals
+#[derive(Debug,Clone,PartialEq)]
+pub(crate) enum Expr {
+    /// a column pattern, resolved through the same regex matching as `column_op`
+    Column(String),
+    Number(f64),
+    Neg(Box<Expr>),
+    Binary(char,Box<Expr>,Box<Expr>),
+}
+
+impl Expr {
+    /// the column patterns referenced by the expression, in the order they appear
+    pub(crate) fn columns(&self) -> Vec<&str> {
+        match self {
+            Expr::Column(pattern) => vec![pattern.as_str()],
+            Expr::Number(_) => vec![],
+            Expr::Neg(operand) => operand.columns(),
+            Expr::Binary(_,lhs,rhs) => {
+                let mut columns = lhs.columns();
+                columns.extend(rhs.columns());
+                columns
+            }
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+/// parses an expression such as `(col_a + col_b) / col_c`. Identifiers start with a letter or underscore and
+/// may contain letters, digits, `_` and `.`. Column patterns containing other characters can be wrapped in
+/// double quotes, e.g. `"b(mm)" * 2`. `^` binds tightest and is right associative, followed by `*`, `/`
+/// and `%`, then `+` and `-`. Parentheses group.
+pub(crate) fn parse(expression:&str) -> Result<Expr,SheetError> {
+    let tokens = tokenize(expression).map_err(SheetError::InvalidExpression)?;
+    let mut parser = Parser { tokens:&tokens, pos:0 };
+    let expr = parser.expression().map_err(|e| SheetError::InvalidExpression(format!("{} in expression '{}'",e,expression)))?;
+    if let Some(token) = parser.peek() {
+        Err(SheetError::InvalidExpression(format!("unexpected token {:?} in expression '{}'",token,expression)))?
+    }
+    Ok(expr)
+}
+
+fn tokenize(expression:&str) -> Result<Vec<Token>,String> {
+    let mut tokens = vec![];
+    let mut chars = expression.char_indices().peekable();
+    while let Some(&(start,c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut ident = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_,'"')) => break,
+                        Some((_,c)) => ident.push(c),
+                        None => Err(format!("unterminated quoted column name starting at position {} in expression '{}'",start,expression))?
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = start;
+                while let Some(&(i,c)) = chars.peek() {
+                    if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    }else if (c == '+' || c == '-') && expression[..i].ends_with(['e','E']) {
+                        // sign of an exponent
+                        end = i + 1;
+                        chars.next();
+                    }else {
+                        break
+                    }
+                }
+                let literal = &expression[start..end];
+                let value = literal.parse::<f64>().map_err(|_| format!("invalid number '{}' in expression '{}'",literal,expression))?;
+                tokens.push(Token::Number(value));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some(&(i,c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == '.' {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    }else {
+                        break
+                    }
+                }
+                tokens.push(Token::Ident(expression[start..end].to_string()));
+            }
+            _ => Err(format!("unexpected character '{}' at position {} in expression '{}'",c,start,expression))?
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens:&'a [Token],
+    pos:usize,
+}
+
+impl Parser<'_> {
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr,String> {
+        let mut lhs = self.term()?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if op != '+' && op != '-' {
+                break
+            }
+            self.next();
+            let rhs = self.term()?;
+            lhs = Expr::Binary(op,Box::new(lhs),Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // term := factor (('*' | '/' | '%') factor)*
+    fn term(&mut self) -> Result<Expr,String> {
+        let mut lhs = self.factor()?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if op != '*' && op != '/' && op != '%' {
+                break
+            }
+            self.next();
+            let rhs = self.factor()?;
+            lhs = Expr::Binary(op,Box::new(lhs),Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // factor := '-' factor | power
+    fn factor(&mut self) -> Result<Expr,String> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.next();
+            return Ok(Expr::Neg(Box::new(self.factor()?)))
+        }
+        self.power()
+    }
+
+    // power := atom ('^' factor)?
+    fn power(&mut self) -> Result<Expr,String> {
+        let base = self.atom()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.next();
+            let exponent = self.factor()?;
+            return Ok(Expr::Binary('^',Box::new(base),Box::new(exponent)))
+        }
+        Ok(base)
+    }
+
+    // atom := number | identifier | '(' expression ')'
+    fn atom(&mut self) -> Result<Expr,String> {
+        match self.next().cloned() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Ident(name)) => Ok(Expr::Column(name)),
+            Some(Token::LParen) => {
+                let expr = self.expression()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("expected closing parenthesis")?
+                }
+            }
+            Some(token) => Err(format!("unexpected token {:?}",token))?,
+            None => Err("unexpected end of input")?
+        }
+    }
+}
@@ -1,157 +1,2105 @@
-use std::error::Error;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::io::BufRead;
 
 use ndarray::{s, Array2, Axis};
 use num_traits::Float;
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
-use regex::Regex;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use regex::{Regex, RegexBuilder};
+
+mod error;
+mod expr;
+mod numeric_view;
+#[cfg(feature = "parquet")]
+mod parquet_output;
+mod sequential;
+mod stream;
+#[cfg(feature = "xlsx")]
+mod xlsx_input;
+
+pub use error::SheetError;
+pub use numeric_view::NumericView;
+pub use sequential::{CumOp, InterpMethod, NanPolicy};
+pub use stream::process_streaming;
 
 pub struct SpreadSheet2D {
     preamble:Vec<String>,
     col_delimeter:String,
+    output_delimeter:Option<String>,
+    quoting:bool,
+    write_header:bool,
     data:Array2<String>,
-    column_headers:Vec<String>
+    column_headers:Vec<String>,
+    number_format:NumberFormat,
+    /// written in place of NaN results of operations
+    nan_token:String,
+    /// whether header patterns ignore case
+    case_insensitive:bool,
+    numeric_options:NumericOptions,
+    line_ending:LineEnding,
+    /// whether `to_string` starts with a byte order mark. Set when the parsed input had one.
+    write_bom:bool,
+    /// wrap every written cell in double quotes, not just the ones that need it
+    quote_all:bool,
+    precision:Precision,
+}
+
+/// arithmetic operations supported by `column_op`
+pub const OPERATIONS:[&str;8] = ["+","-","*","/","^","%","min","max"];
+
+/// single operand operations supported by `column_unary_op`
+pub const UNARY_OPERATIONS:[&str;7] = ["neg","recip","abs","sqrt","log10","ln","exp"];
+
+/// comparison operations supported by `column_op`, producing `true`/`false` columns
+pub const COMPARISONS:[&str;5] = [">","<",">=","<=","=="];
+
+/// statistics that reduce a set of values to a single number
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Stat {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    /// sample standard deviation (n-1 denominator)
+    Std,
+    Count,
+    Median,
+}
+
+impl Stat {
+    /// computes the statistic over `values`. NaN entries must be removed by the caller.
+    /// Statistics that are undefined for the given number of values return NaN.
+    pub fn compute(&self,values:&[f64]) -> f64 {
+        let n = values.len();
+        match self {
+            // folding from 0 rather than `sum` keeps the sum of no values from being -0
+            Stat::Sum => values.iter().fold(0.,|acc,x| acc + x),
+            Stat::Count => n as f64,
+            Stat::Mean => {
+                if n == 0 {
+                    f64::NAN
+                }else {
+                    values.iter().sum::<f64>() / n as f64
+                }
+            }
+            Stat::Min => values.iter().cloned().reduce(f64::min).unwrap_or(f64::NAN),
+            Stat::Max => values.iter().cloned().reduce(f64::max).unwrap_or(f64::NAN),
+            Stat::Std => {
+                if n < 2 {
+                    return f64::NAN
+                }
+                let mean = Stat::Mean.compute(values);
+                let sum_sq:f64 = values.iter().map(|x| (x - mean).powi(2)).sum();
+                (sum_sq / (n - 1) as f64).sqrt()
+            }
+            Stat::Median => {
+                if n == 0 {
+                    return f64::NAN
+                }
+                // sort a copy so the caller's data is left untouched
+                let mut sorted = values.to_vec();
+                sorted.sort_by(|a,b| a.total_cmp(b));
+                if n.is_multiple_of(2) {
+                    (sorted[n/2 - 1] + sorted[n/2]) / 2.
+                }else {
+                    sorted[n/2]
+                }
+            }
+        }
+    }
+}
+
+/// a cell read as a number the way `to_json`, `to_parquet` and `infer_schema` see it
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub(crate) enum Number {
+    Integer(i64),
+    Float(f64),
 }
 
-impl ToString for SpreadSheet2D {
-    fn to_string(&self) -> String {
+impl Number {
+    /// parses a cell with the thousands and decimal separators of `opts`, the same way `columns_numeric`
+    /// reads it. Integers are kept as integers so they aren't written with a trailing `.0`. Non-finite values
+    /// such as `NaN` have no JSON representation and are not numbers here, and neither are missing values,
+    /// whatever `na_fill` says.
+    pub(crate) fn parse(cell:&str,opts:&NumericOptions) -> Option<Self> {
+        let cell = opts.normalize(cell);
+        if let Ok(x) = cell.parse::<i64>() {
+            return Some(Number::Integer(x))
+        }
+        cell.parse::<f64>().ok().filter(|x| x.is_finite()).map(Number::Float)
+    }
+}
+
+/// the type of a column reported by `SpreadSheet2D::infer_schema`, which also decides how `to_parquet` and
+/// `to_json` write the cells of the column. Empty cells are missing values: they don't affect the inferred type, and `to_parquet` and
+/// `to_json` write them as nulls whatever the type of their column.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ColumnType {
+    /// every non-empty cell parses as an i64
+    Integer,
+    /// every non-empty cell parses as a finite f64
+    Float,
+    /// every non-empty cell is `true` or `false`, ignoring case
+    Boolean,
+    String,
+}
 
-        let mut s = if self.preamble.is_empty() {
-            self.preamble.join("\n")
+impl ColumnType {
+    /// the narrowest type every cell of `cells` fits, reading numbers with `opts`. Empty cells are ignored and
+    /// a column with nothing but empty cells is a `String` column.
+    fn infer<'a>(cells:impl Iterator<Item = &'a String> + Clone,opts:&NumericOptions) -> Self {
+        let mut non_empty = cells.filter(|cell| !cell.is_empty()).peekable();
+        if non_empty.peek().is_none() {
+            return ColumnType::String
+        }
+        if non_empty.clone().all(|cell| matches!(Number::parse(cell, opts),Some(Number::Integer(_)))) {
+            ColumnType::Integer
+        }else if non_empty.clone().all(|cell| Number::parse(cell, opts).is_some()) {
+            ColumnType::Float
+        }else if non_empty.all(|cell| cell.eq_ignore_ascii_case("true") || cell.eq_ignore_ascii_case("false")) {
+            ColumnType::Boolean
         }else {
-            let mut s = self.preamble.join("\n");
-            s.push('\n');
-            s
+            ColumnType::String
+        }
+    }
+}
+
+/// which rows `SpreadSheet2D::join` keeps
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum JoinKind {
+    /// only rows whose key appears in both spreadsheets
+    Inner,
+    /// every row of the left spreadsheet, matched or not
+    Left,
+}
+
+/// methods for `SpreadSheet2D::normalize_column`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum NormMethod {
+    /// scales to [0,1] by subtracting the minimum and dividing by the range
+    MinMax,
+    /// subtracts the mean and divides by the sample standard deviation
+    ZScore,
+}
+
+/// rounding modes for `SpreadSheet2D::round_column`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum RoundMode {
+    /// to the nearest integer, rounding half way cases away from zero
+    Nearest,
+    Floor,
+    Ceil,
+    /// towards zero
+    Trunc,
+}
+
+impl RoundMode {
+    pub fn apply(&self,x:f64) -> f64 {
+        match self {
+            RoundMode::Nearest => x.round(),
+            RoundMode::Floor => x.floor(),
+            RoundMode::Ceil => x.ceil(),
+            RoundMode::Trunc => x.trunc(),
+        }
+    }
+}
+
+/// line terminator written after the preamble lines, the header and each row
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// floating point type used for the arithmetic of `column_op`, `column_unary_op`, `column_scalar_op` and
+/// `column_expr`. `F32` halves the memory of the parsed operand columns, at the cost of about 7 significant
+/// digits instead of 16 and a range of roughly ±3.4e38, beyond which results become infinite. Cells are
+/// always stored as strings, so the precision only matters while a calculation runs. f32 results are written
+/// with the shortest representation that round-trips as f32, e.g. `0.1` rather than `0.10000000149011612`.
+/// The cache returned by `numeric_view` is always f64; use `numeric_view_as::<f32>` for an f32 cache.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Precision {
+    #[default]
+    F64,
+    F32,
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for f64 {}
+    impl Sealed for f32 {}
+}
+
+/// the float types `Precision` selects between, also usable as the element type of a `NumericView`.
+/// Implemented for f64 and f32 only.
+pub trait Compute: Float + Send + Sync + sealed::Sealed {
+    /// the f64 a result is written as
+    fn to_output(self) -> f64;
+}
+
+impl Compute for f64 {
+    fn to_output(self) -> f64 {
+        self
+    }
+}
+
+impl Compute for f32 {
+    fn to_output(self) -> f64 {
+        // going through the shortest decimal representation of the f32 keeps the widening from adding digits
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+
+/// options controlling how delimited text is parsed into a `SpreadSheet2D`
+#[derive(Debug,Clone)]
+pub struct ParseOptions {
+    /// delimiter between fields. With a `regex:` prefix the rest is a regex matching the delimiter, e.g.
+    /// `regex:\s+` for columns separated by any mix of tabs and spaces. A regex can't be used to join
+    /// fields, so such a spreadsheet is written tab delimited unless `set_output_delimiter` says otherwise.
+    pub col_delimeter:String,
+    /// number of lines preceding the header that are kept verbatim as the preamble
+    pub line_offset:usize,
+    /// regex matching additional preamble lines. After the first `line_offset` lines, every leading line
+    /// matching the pattern is treated as preamble and the first line that doesn't match is the header.
+    pub preamble_pattern:Option<String>,
+    /// respect RFC 4180 double quoting. Quoted fields may contain the delimiter and escaped quotes (`""`).
+    /// Quoted fields spanning multiple lines are not supported.
+    pub quoting:bool,
+    /// split fields on runs of whitespace, mirroring `str::split_whitespace`. Leading and trailing whitespace
+    /// is ignored and `col_delimeter` is only used when writing the spreadsheet back out.
+    pub whitespace_delimited:bool,
+    /// data lines (after the header) starting with this prefix are skipped. Skipped lines are discarded and
+    /// are not written back out by `to_string`.
+    pub comment_prefix:Option<String>,
+    /// whether the first line after the preamble is a header. If false, headers `col_1`, `col_2`, ... are
+    /// synthesized from the field count of the first row and the header is omitted when writing, unless
+    /// `SpreadSheet2D::set_write_header` says otherwise.
+    pub has_header:bool,
+    /// trim leading and trailing whitespace from every header and field after splitting
+    pub trim:bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self { col_delimeter: "\t".to_string(), line_offset: 0, preamble_pattern: None, quoting: false, whitespace_delimited: false, comment_prefix: None, has_header: true, trim: false }
+    }
+}
+
+/// how computed values are written to cells
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum NumberFormat {
+    /// the shortest representation that parses back to the same value, as written by `f64::to_string`
+    #[default]
+    Default,
+    /// a fixed number of decimal places
+    Fixed(usize),
+    /// scientific notation, optionally with a fixed number of decimal places
+    Exponential(Option<usize>),
+    /// rounds to at most the given number of decimal places, or full precision if `None`, and removes
+    /// trailing zeros and a trailing decimal point, so `3.0` is written as `3` and `2.50` as `2.5`
+    TrimTrailingZeros(Option<usize>),
+}
+
+impl NumberFormat {
+    /// parses a Rust style format string: `{}`, `{:.4}`, `{:e}` or `{:.4e}`
+    pub fn parse(spec:&str) -> Result<Self,SheetError> {
+        let invalid = || format!("unsupported number format '{}'. Expected {{}}, {{:.N}}, {{:e}} or {{:.Ne}}",spec);
+        let Some(inner) = spec.strip_prefix('{').and_then(|x| x.strip_suffix('}')) else {
+            Err(invalid())?
+        };
+        if inner.is_empty() {
+            return Ok(NumberFormat::Default)
+        }
+        let Some(inner) = inner.strip_prefix(':') else {
+            Err(invalid())?
+        };
+        let (precision,exponential) = match inner.strip_suffix('e') {
+            Some(precision) => (precision,true),
+            None => (inner,false),
         };
+        let decimals = match precision.strip_prefix('.') {
+            Some(decimals) => Some(decimals.parse::<usize>().map_err(|_| invalid())?),
+            None if precision.is_empty() => None,
+            None => Err(invalid())?,
+        };
+        match (decimals,exponential) {
+            (decimals,true) => Ok(NumberFormat::Exponential(decimals)),
+            (Some(decimals),false) => Ok(NumberFormat::Fixed(decimals)),
+            (None,false) => Err(invalid())?,
+        }
+    }
 
-        s.push_str(&self.column_headers.join(&self.col_delimeter));
-        s.push('\n');
+    pub fn format(&self,x:f64) -> String {
+        match self {
+            NumberFormat::Default => x.to_string(),
+            NumberFormat::Fixed(decimals) => format!("{:.*}",decimals,x),
+            NumberFormat::Exponential(None) => format!("{:e}",x),
+            NumberFormat::Exponential(Some(decimals)) => format!("{:.*e}",decimals,x),
+            NumberFormat::TrimTrailingZeros(None) => x.to_string(),
+            NumberFormat::TrimTrailingZeros(Some(decimals)) => {
+                let s = format!("{:.*}",decimals,x);
+                if !s.contains('.') {
+                    return s
+                }
+                let trimmed = s.trim_end_matches('0').trim_end_matches('.');
+                if trimmed == "-0" { "0".to_string() } else { trimmed.to_string() }
+            }
+        }
+    }
+}
 
-        self.data.axis_iter(Axis(0)).for_each(|row|{
-            let row_str = row.to_vec().join(&self.col_delimeter);
-            s.push_str(&row_str);
-            s.push('\n');
-        });
-        s
+/// options controlling how cells are parsed to numbers
+#[derive(Debug,Clone)]
+pub struct NumericOptions {
+    /// value used for cells matching one of `na_tokens`. If None, these cells parse to NaN.
+    /// The fill is applied when a cell is parsed, so before any arithmetic is performed on it.
+    pub na_fill:Option<f64>,
+    /// cell values recognized as missing data
+    pub na_tokens:Vec<String>,
+    /// digit grouping character removed before parsing, e.g. `,` for `1,234.5`
+    pub thousands_sep:Option<char>,
+    /// decimal mark replaced with `.` before parsing, e.g. `,` together with a `.` thousands separator for
+    /// European style `1.234,56`
+    pub decimal_sep:Option<char>,
+}
+
+impl Default for NumericOptions {
+    fn default() -> Self {
+        Self {
+            na_fill: None,
+            na_tokens: ["NA","null","","-"].iter().map(|x|x.to_string()).collect(),
+            thousands_sep: None,
+            decimal_sep: None,
+        }
+    }
+}
+
+impl NumericOptions {
+    /// parses a cell to f64. Missing values are replaced with `na_fill` and anything else that fails to
+    /// parse is NaN.
+    pub fn parse(&self,cell:&str) -> f64 {
+        self.try_parse(cell).unwrap_or(f64::NAN)
+    }
+
+    /// parses a cell to f64, returning None if it is not a number. Missing values are only accepted if
+    /// `na_fill` is set.
+    pub fn try_parse(&self,cell:&str) -> Option<f64> {
+        if let Some(fill) = self.na_fill {
+            if self.na_tokens.iter().any(|token| token == cell) {
+                return Some(fill)
+            }
+        }
+        self.normalize(cell).parse::<f64>().ok()
+    }
+
+    /// removes the thousands separator from `cell` and replaces the decimal mark with `.`
+    fn normalize<'a>(&self,cell:&'a str) -> Cow<'a,str> {
+        if self.thousands_sep.is_none() && self.decimal_sep.is_none() {
+            return Cow::Borrowed(cell)
+        }
+        Cow::Owned(cell.chars().filter(|&c| Some(c) != self.thousands_sep).map(|c|{
+            if Some(c) == self.decimal_sep { '.' } else { c }
+        }).collect())
+    }
+}
+
+/// the UTF-8 byte order mark some tools, notably Excel, write at the start of a file
+const BOM:char = '\u{FEFF}';
+
+/// removes a leading byte order mark, returning the rest of `s` and whether one was found
+fn strip_bom(s:&str) -> (&str,bool) {
+    match s.strip_prefix(BOM) {
+        Some(rest) => (rest,true),
+        None => (s,false),
+    }
+}
+
+/// consumes a leading byte order mark from `reader`, returning whether one was found
+pub(crate) fn skip_bom(reader:&mut impl BufRead) -> std::io::Result<bool> {
+    let bom = [0xEF,0xBB,0xBF];
+    if reader.fill_buf()?.starts_with(&bom) {
+        reader.consume(bom.len());
+        return Ok(true)
+    }
+    Ok(false)
+}
+
+/// the preamble and column headers of a delimited file, read without parsing the data body
+#[derive(Debug,Clone)]
+pub struct SheetHeader {
+    pub preamble:Vec<String>,
+    pub column_headers:Vec<String>,
+}
+
+/// reads only the preamble and header of a delimited file, stopping before the data body. Headers are
+/// determined the same way as `SpreadSheet2D::from_string_with_options`.
+pub fn read_header(mut reader:impl BufRead,opts:&ParseOptions) -> Result<SheetHeader,SheetError> {
+    let matcher = PreambleMatcher::new(opts)?;
+    let splitter = LineSplitter::new(opts)?;
+    skip_bom(&mut reader)?;
+    let mut lines = reader.lines();
+    let mut preamble = vec![];
+    let mut next_line = None;
+    for line in lines.by_ref() {
+        let line = line?;
+        if matcher.matches(preamble.len(), &line) {
+            preamble.push(line);
+        }else {
+            next_line = Some(line);
+            break
+        }
+    }
+    let mut line_number = preamble.len();
+    let column_headers = loop {
+        line_number += 1;
+        let line = match next_line.take() {
+            Some(line) => line,
+            None => match lines.next() {
+                Some(line) => line?,
+                None => Err(format!("unexpected end of input: expected a header row after {} preamble line(s)",preamble.len()))?
+            }
+        };
+        if opts.has_header {
+            break splitter.split(&line).map_err(|message| SheetError::MalformedLine { line: line_number, message })?
+        }
+        if opts.comment_prefix.as_ref().is_some_and(|prefix| line.starts_with(prefix.as_str())) {
+            continue
+        }
+        let n = splitter.split(&line).map_err(|message| SheetError::MalformedLine { line: line_number, message })?.len();
+        break synthesize_headers(n)
+    };
+    Ok(SheetHeader { preamble, column_headers })
+}
+
+impl Display for SpreadSheet2D {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_with_delimiter(self.output_delimiter()))
     }
 }
 
 impl SpreadSheet2D {
     pub fn from_string(s:String,col_delimeter:&str,line_offset:usize) -> Self {
-        
-        let mut rows = s.lines();
-    
-        let mut preamble = Vec::<String>::new();
-        for _ in 0..line_offset {
-            if let Some(line) = rows.next() {
-                preamble.push(line.to_string());
+        Self::try_from_string(s, col_delimeter, line_offset).unwrap()
+    }
+
+    pub fn try_from_string(s:String,col_delimeter:&str,line_offset:usize) -> Result<Self,SheetError> {
+        let opts = ParseOptions {
+            col_delimeter: col_delimeter.to_string(),
+            line_offset,
+            ..Default::default()
+        };
+        Self::from_string_with_options(s, &opts)
+    }
+
+    /// parses a spreadsheet where every leading line matching the regex `preamble_pattern` (e.g. `^[#%]`)
+    /// is part of the preamble and the first line that doesn't match is the header
+    pub fn from_string_auto_preamble(s:String,col_delimeter:&str,preamble_pattern:&str) -> Result<Self,SheetError> {
+        let opts = ParseOptions {
+            col_delimeter: col_delimeter.to_string(),
+            preamble_pattern: Some(preamble_pattern.to_string()),
+            ..Default::default()
+        };
+        Self::from_string_with_options(s, &opts)
+    }
+
+    /// parses comma separated values with RFC 4180 quoting
+    pub fn from_csv(s:String,line_offset:usize) -> Result<Self,SheetError> {
+        let opts = ParseOptions {
+            col_delimeter: ",".to_string(),
+            line_offset,
+            quoting: true,
+            ..Default::default()
+        };
+        Self::from_string_with_options(s, &opts)
+    }
+
+    /// parses columns separated by runs of whitespace, as is common for aligned scientific output.
+    /// The spreadsheet is written back out with single space delimiters.
+    pub fn from_whitespace_delimited(s:String,line_offset:usize) -> Result<Self,SheetError> {
+        let opts = ParseOptions {
+            col_delimeter: " ".to_string(),
+            line_offset,
+            whitespace_delimited: true,
+            ..Default::default()
+        };
+        Self::from_string_with_options(s, &opts)
+    }
+
+    /// parses fixed-width columns without a delimiter. The header and every data row are cut into fields of
+    /// `widths` characters, each trimmed of surrounding whitespace, and characters past the total width are
+    /// ignored. Fails listing every row shorter than the total width. The spreadsheet is written back out
+    /// tab delimited.
+    pub fn from_fixed_width(s:String,widths:&[usize],line_offset:usize) -> Result<Self,SheetError> {
+        if widths.is_empty() {
+            Err("at least one column width is required")?
+        }
+        let total_width:usize = widths.iter().sum();
+        let (body,bom) = strip_bom(&s);
+        let mut lines = body.lines();
+        let preamble:Vec<String> = lines.by_ref().take(line_offset).map(|line| line.to_string()).collect();
+        let Some(header) = lines.next() else {
+            Err(format!("unexpected end of input: expected a header row after {} preamble line(s)",preamble.len()))?
+        };
+
+        let mut too_short = vec![];
+        let mut cells = vec![];
+        for (i,line) in std::iter::once(header).chain(lines).enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            match split_fixed_width(line, widths) {
+                Some(fields) => cells.extend(fields),
+                None => too_short.push(format!("line {} ({} characters)",line_offset + i + 1,line.chars().count())),
             }
         }
+        if !too_short.is_empty() {
+            Err(format!("row(s) shorter than the total column width of {}: {}",total_width,too_short.join(", ")))?
+        }
+
+        let column_headers:Vec<String> = cells.drain(..widths.len()).collect();
+        let n_rows = cells.len() / widths.len();
+        let data = Array2::from_shape_vec((n_rows, widths.len()), cells)?;
+        let mut sheet = Self::from_parts(preamble, "\t", column_headers, data);
+        sheet.write_bom = bom;
+        Ok(sheet)
+    }
+
+    /// wraps a numeric array in a spreadsheet with no preamble, one column per header. Elements are written
+    /// the same way as the results of operations with the default number format. The inverse of
+    /// `to_numeric_array`.
+    pub fn from_numeric_array(data:Array2<f64>,headers:Vec<String>,col_delimeter:&str) -> Result<Self,SheetError> {
+        if headers.is_empty() {
+            Err("spreadsheet must have at least one column header")?
+        }
+        if headers.len() != data.ncols() {
+            Err(format!("expected {} header(s) for {} column(s), found {}",data.ncols(),data.ncols(),headers.len()))?
+        }
+        let format = NumberFormat::default();
+        let data = data.map(|&x| format.format(x));
+        Ok(Self::from_parts(vec![], col_delimeter, headers, data))
+    }
+
+    /// parses a spreadsheet according to `opts`
+    pub fn from_string_with_options(s:String,opts:&ParseOptions) -> Result<Self,SheetError> {
+
+        let matcher = PreambleMatcher::new(opts)?;
+        let splitter = LineSplitter::new(opts)?;
+        let (body,bom) = strip_bom(&s);
+        let mut rows = body.lines().peekable();
     
-        if !preamble.is_empty() {
-            println!("{}",preamble.join("\n"));
+        let mut preamble = Vec::<String>::new();
+        while let Some(line) = rows.next_if(|line| matcher.matches(preamble.len(), line)) {
+            preamble.push(line.to_string());
         }
+        let line_offset = preamble.len();
+
+        let column_headers:Vec<_> = if opts.has_header {
+            match rows.next() {
+                Some(header) => splitter.split(header).map_err(|message| SheetError::MalformedLine { line: line_offset + 1, message })?,
+                None => Err(format!("unexpected end of input: expected a header row after {} preamble line(s)",line_offset))?
+            }
+        }else {
+            vec![]
+        };
 
-        let column_headers:Vec<_> = rows.next().expect("unexpected end of rows!").split(col_delimeter).map(|x|x.to_string()).collect();
+        // line numbers are 1-based and account for the preamble and header
+        let first_line = line_offset + if opts.has_header { 2 } else { 1 };
+        let rows:Vec<(usize,&str)> = rows.enumerate()
+            .map(|(row_idx,row)| (first_line + row_idx,row))
+            .filter(|(_,row)| !opts.comment_prefix.as_ref().is_some_and(|prefix| row.starts_with(prefix.as_str())))
+            .collect();
+
+        // without a header row, synthesize col_1, col_2, ... from the field count of the first row
+        let column_headers = if opts.has_header {
+            column_headers
+        }else {
+            match rows.first() {
+                Some(&(line_number,row)) => {
+                    let n = splitter.split(row).map_err(|message| SheetError::MalformedLine { line: line_number, message })?.len();
+                    synthesize_headers(n)
+                }
+                None => Err(format!("unexpected end of input: expected a data row after {} preamble line(s)",line_offset))?
+            }
+        };
         
+        let mut sheet = Self::from_rows(preamble, column_headers, &rows, opts)?;
+        sheet.write_bom = bom;
+        Ok(sheet)
+
+    }
+
+    /// builds a spreadsheet from data rows paired with their 1-based line numbers, which are used to report
+    /// malformed rows
+    pub(crate) fn from_rows(preamble:Vec<String>,column_headers:Vec<String>,rows:&[(usize,&str)],opts:&ParseOptions) -> Result<Self,SheetError> {
+
         let n_columns = column_headers.len();
+        // e.g. a blank header line with whitespace_delimited
+        if n_columns == 0 {
+            Err("spreadsheet must have at least one column header")?
+        }
+        let splitter = LineSplitter::new(opts)?;
     
         // read rows into a flat vector
-        let mut table_elements = vec![];
-    
-        let mut col_counter = 0;
-        rows.for_each(|row|{
-            row.split(col_delimeter).for_each(|entry|{
-                col_counter += 1;
-                table_elements.push(entry.to_string());
-            });
-            if col_counter != n_columns {
-                panic!("issue with reading row! Missing {} element(s)",n_columns - col_counter);
-            }else {
-                col_counter = 0;
+        let mut table_elements = Vec::with_capacity(rows.len() * n_columns);
+        for &(line_number,row) in rows {
+            let n_before = table_elements.len();
+            splitter.split_into(row, &mut table_elements).map_err(|message| SheetError::MalformedLine { line: line_number, message })?;
+            let n_entries = table_elements.len() - n_before;
+            if n_entries != n_columns {
+                Err(SheetError::RowLengthMismatch { line: line_number, expected: n_columns, got: n_entries })?
             }
-        });
-    
-        let n_rows = table_elements.len() / n_columns;
+        }
     
-        let data = Array2::from_shape_vec((n_rows,n_columns),table_elements)
-        .expect("incorrect dimensions for array construction");
+        // every row was checked to have n_columns fields, so a mismatch means that invariant is broken. Any
+        // other failure of the array construction is reported as SheetError::Shape. Zero rows give a valid
+        // empty (0, n_columns) array.
+        let n_rows = rows.len();
+        if table_elements.len() != n_rows * n_columns {
+            Err(format!("cannot build a ({}, {}) table from {} element(s)",n_rows,n_columns,table_elements.len()))?
+        }
+        let data = Array2::from_shape_vec((n_rows,n_columns),table_elements)?;
 
+        let mut sheet = Self::from_parts(preamble, splitter.output_delimiter(), column_headers, data);
+        sheet.quoting = opts.quoting;
+        // synthesized headers are not written back out unless requested
+        sheet.write_header = opts.has_header;
+        Ok(sheet)
+    }
+
+    /// constructs a spreadsheet with default formatting options. The caller is responsible for making sure
+    /// the number of headers matches the number of data columns.
+    fn from_parts(preamble:Vec<String>,col_delimeter:&str,column_headers:Vec<String>,data:Array2<String>) -> Self {
         Self {
             col_delimeter: col_delimeter.to_owned(),
+            output_delimeter: None,
+            quoting: false,
+            write_header: true,
             data,
             column_headers,
             preamble,
+            number_format: NumberFormat::default(),
+            nan_token: "NaN".to_string(),
+            case_insensitive: false,
+            numeric_options: NumericOptions::default(),
+            line_ending: LineEnding::default(),
+            write_bom: false,
+            quote_all: false,
+            precision: Precision::default(),
+        }
+    }
+
+    /// serializes the data rows as a JSON array of objects keyed by column header. Every column is typed by
+    /// `infer_schema`, the same way `to_parquet` types its fields: cells of Integer and Float columns are
+    /// written as JSON numbers, read with the spreadsheet's `NumericOptions` like `columns_numeric`, cells of
+    /// Boolean columns as booleans and all other cells as strings. Empty cells are null, see `ColumnType`.
+    /// Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String,SheetError> {
+        let schema = self.infer_schema();
+        let rows:Vec<serde_json::Value> = self.data.axis_iter(Axis(0)).map(|row|{
+            let object:serde_json::Map<String,serde_json::Value> = schema.iter().zip(row.iter()).map(|((header,column_type),cell)|{
+                (header.to_string(),self.json_value(cell, *column_type))
+            }).collect();
+            serde_json::Value::Object(object)
+        }).collect();
+        Ok(serde_json::to_string_pretty(&rows)?)
+    }
+
+    /// a cell as the JSON value of its column type
+    #[cfg(feature = "json")]
+    fn json_value(&self,cell:&str,column_type:ColumnType) -> serde_json::Value {
+        if cell.is_empty() {
+            return serde_json::Value::Null
+        }
+        let number = match column_type {
+            ColumnType::Integer | ColumnType::Float => Number::parse(cell, &self.numeric_options),
+            ColumnType::Boolean => return serde_json::Value::Bool(cell.eq_ignore_ascii_case("true")),
+            ColumnType::String => None,
+        };
+        let number = number.and_then(|number| match (number,column_type) {
+            (Number::Integer(x),ColumnType::Integer) => Some(serde_json::Number::from(x)),
+            (Number::Integer(x),_) => serde_json::Number::from_f64(x as f64),
+            (Number::Float(x),_) => serde_json::Number::from_f64(x),
+        });
+        match number {
+            Some(number) => serde_json::Value::Number(number),
+            None => serde_json::Value::String(cell.to_string()),
+        }
+    }
+
+    /// sets the delimiter used when writing the spreadsheet, independent of the delimiter it was parsed with
+    pub fn set_output_delimiter(&mut self,delim:&str) {
+        self.output_delimeter = Some(delim.to_string());
+    }
+
+    /// sets whether the header row is written by `to_string`
+    pub fn set_write_header(&mut self,write_header:bool) {
+        self.write_header = write_header;
+    }
+
+    /// sets whether `to_string` wraps cells containing the delimiter or a double quote in double quotes, as
+    /// in RFC 4180. Parsing with `ParseOptions::quoting` turns this on.
+    pub fn set_quoting(&mut self,quoting:bool) {
+        self.quoting = quoting;
+    }
+
+    /// sets the line terminator used by `to_string`. Defaults to `LineEnding::Lf`.
+    pub fn set_line_ending(&mut self,ending:LineEnding) {
+        self.line_ending = ending;
+    }
+
+    /// sets the float type used by the arithmetic operations, see `Precision`. Defaults to `Precision::F64`.
+    pub fn set_precision(&mut self,precision:Precision) {
+        self.precision = precision;
+    }
+
+    /// sets whether `to_string` wraps every cell, including the header, in double quotes with quotes inside
+    /// cells doubled. Without it only cells containing the delimiter or a quote are quoted, and only if the
+    /// spreadsheet was parsed with quoting.
+    pub fn set_quote_all(&mut self,quote_all:bool) {
+        self.quote_all = quote_all;
+    }
+
+    /// sets whether `to_string` starts with a UTF-8 byte order mark. Parsing turns this on if the input
+    /// started with one, so files exported from Excel keep their BOM.
+    pub fn set_write_bom(&mut self,write_bom:bool) {
+        self.write_bom = write_bom;
+    }
+
+    /// whether `to_string` starts with a byte order mark
+    pub fn write_bom(&self) -> bool {
+        self.write_bom
+    }
+
+    /// writes the spreadsheet using `delim` between columns. The preamble is written unchanged.
+    pub fn to_string_with_delimiter(&self,delim:&str) -> String {
+        let mut s = self.head_to_string(delim);
+        s.push_str(&self.rows_to_string(delim));
+        s
+    }
+
+    /// the delimiter used by `to_string`
+    pub(crate) fn output_delimiter(&self) -> &str {
+        self.output_delimeter.as_deref().unwrap_or(&self.col_delimeter)
+    }
+
+    /// writes the preamble and, if enabled, the header row
+    pub(crate) fn head_to_string(&self,delim:&str) -> String {
+
+        let newline = self.line_ending.as_str();
+        let mut s = if self.write_bom { BOM.to_string() } else { String::new() };
+        if !self.preamble.is_empty() {
+            s.push_str(&self.preamble.join(newline));
+            s.push_str(newline);
+        }
+
+        if self.write_header {
+            s.push_str(&self.join_row(self.column_headers.iter(),delim));
+            s.push_str(newline);
+        }
+        s
+    }
+
+    /// writes the data rows only
+    pub(crate) fn rows_to_string(&self,delim:&str) -> String {
+        let newline = self.line_ending.as_str();
+        let mut s = String::new();
+        self.data.axis_iter(Axis(0)).for_each(|row|{
+            let row_str = self.join_row(row.iter(),delim);
+            s.push_str(&row_str);
+            s.push_str(newline);
+        });
+        s
+    }
+
+    /// renders the spreadsheet as a GitHub flavored Markdown table. If `include_preamble` is set and the
+    /// spreadsheet has a preamble, it is emitted as a fenced code block above the table.
+    pub fn to_markdown(&self,include_preamble:bool) -> String {
+        let escape = |cell:&String| cell.replace('|', "\\|");
+        let md_row = |cells:Vec<String>| format!("| {} |\n",cells.join(" | "));
+
+        let mut s = String::new();
+        if include_preamble && !self.preamble.is_empty() {
+            s.push_str("```\n");
+            s.push_str(&self.preamble.join("\n"));
+            s.push_str("\n```\n\n");
         }
 
+        s.push_str(&md_row(self.column_headers.iter().map(escape).collect()));
+        s.push_str(&md_row(self.column_headers.iter().map(|_| "---".to_string()).collect()));
+        self.data.axis_iter(Axis(0)).for_each(|row|{
+            s.push_str(&md_row(row.iter().map(escape).collect()));
+        });
+        s
+    }
+
+    /// joins the cells of a row with the delimiter, re-quoting cells if quoting is enabled or quoting every cell
+    /// with `quote_all`
+    fn join_row<'a>(&self,cells:impl Iterator<Item = &'a String>,delim:&str) -> String {
+        let cells:Vec<Cow<str>> = cells.map(|cell|{
+            if self.quote_all || (self.quoting && (cell.contains(delim) || cell.contains('"'))) {
+                Cow::Owned(format!("\"{}\"",cell.replace('"', "\"\"")))
+            }else {
+                Cow::Borrowed(cell.as_str())
+            }
+        }).collect();
+        cells.join(delim)
     }
 
     pub fn columns_numeric(&self) -> Vec<Vec<f64>> {
         let cols = self.data.columns();
         cols.into_iter().map(|col| {
             let num_col:Vec<_> = col.iter().map(|elem| {
-                elem.parse::<f64>().unwrap_or(f64::NAN)
+                self.numeric_options.parse(elem)
             }).collect();
             num_col
         }).collect()
     }
 
-    fn do_operation(col1:&[f64],col2:&[f64],operation:&str) -> Result<Vec<f64>,Box<dyn Error>> {
+    /// same as `columns_numeric`, but fails on the first cell that cannot be parsed, reporting its 0-based
+    /// data row, its column and the offending value
+    pub fn columns_numeric_strict(&self) -> Result<Vec<Vec<f64>>,SheetError> {
+        (0..self.column_headers.len()).map(|idx| self.parse_column_strict(idx)).collect()
+    }
+
+    /// parses the matched column, failing on the first cell that cannot be parsed like `columns_numeric_strict`
+    pub fn column_numeric_strict(&self,col:&str) -> Result<Vec<f64>,SheetError> {
+        let idx = self.resolve_column(col)?;
+        self.parse_column_strict(idx)
+    }
+
+    /// parses the whole table into a row-major `Array2<f64>` where element `[[row, col]]` is the cell in
+    /// data row `row` (0-based, excluding preamble and header) under `column_headers()[col]`. Cells that fail
+    /// to parse are NaN, following the spreadsheet's `NumericOptions`.
+    pub fn to_numeric_array(&self) -> Array2<f64> {
+        self.data.map(|x| self.numeric_options.parse(x))
+    }
+
+    /// iterates over the data rows parsed to f64. Cells that fail to parse are NaN.
+    pub fn numeric_rows(&self) -> impl Iterator<Item = Vec<f64>> + '_ {
+        self.data.axis_iter(Axis(0)).map(|row|{
+            row.iter().map(|elem| self.numeric_options.parse(elem)).collect()
+        })
+    }
+
+    /// iterates over the columns in order, pairing each header with a lazy iterator over its cells
+    pub fn iter_columns(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &str> + '_)> + '_ {
+        self.column_headers.iter().zip(self.data.columns()).map(|(header,col)|{
+            (header.as_str(), col.into_iter().map(|x| x.as_str()))
+        })
+    }
+
+    /// same as `iter_columns`, parsing each cell to f64 as it is visited. Cells that fail to parse are NaN.
+    pub fn iter_numeric_columns(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = f64> + '_)> + '_ {
+        self.iter_columns().map(|(header,col)|{
+            (header, col.map(|x| self.numeric_options.parse(x)))
+        })
+    }
+
+    /// element-wise binary operations. `^` raises the left operand to the power of the right, `%` is the
+    /// remainder with the sign of the left operand, and `min`/`max` are pair-wise with NaN propagating.
+    fn do_operation<F:Float + Send + Sync>(col1:&[F],col2:&[F],operation:&str) -> Result<Vec<F>,SheetError> {
         match operation {
             "*" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a * b).collect()),
             "/" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a / b).collect()),
             "-" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a - b).collect()),
             "+" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a + b).collect()),
-            _=> Err(format!("unknown operation {}",operation))?
+            "^" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a.powf(b)).collect()),
+            "%" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a % b).collect()),
+            "min" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| if a.is_nan() || b.is_nan() { F::nan() } else { a.min(b) }).collect()),
+            "max" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| if a.is_nan() || b.is_nan() { F::nan() } else { a.max(b) }).collect()),
+            _=> Err(SheetError::UnknownOperation(operation.to_string()))?
         }
     }
 
-    pub fn exract_column(&self,column_label_pattern:&str) -> Result<Vec<f32>,Box<dyn Error>> {
-        let idx = column_index(&self.column_headers, column_label_pattern)?;
+    pub fn exract_column(&self,column_label_pattern:&str) -> Result<Vec<f32>,SheetError> {
+        let idx = self.resolve_column(column_label_pattern)?;
         Ok(self.extract_column_from_idx(idx))
     }
 
-    fn extract_column_from_idx(&self,column_idx:usize) -> Vec<f32> {
-        self.data.slice(s![.., column_idx]).map(|x| x.parse::<f32>().unwrap_or(f32::NAN)).to_vec()
+    /// same as `exract_column`, but fails with `SheetError::InvalidNumber` on the first cell that cannot be
+    /// parsed instead of returning NaN for it
+    pub fn try_extract_column(&self,column_label_pattern:&str) -> Result<Vec<f32>,SheetError> {
+        let idx = self.resolve_column(column_label_pattern)?;
+        Ok(self.parse_column_strict(idx)?.into_iter().map(|x| x as f32).collect())
     }
 
-    pub fn column_op(&mut self,col1:&str,operation:&str,col2:&str,new_col_name:&str) -> Result<(),Box<dyn Error>> {
+    /// extracts a column by its 0-based position rather than by header pattern
+    pub fn extract_column_by_index(&self,idx:usize) -> Result<Vec<f32>,SheetError> {
+        let n_columns = self.column_headers.len();
+        if idx >= n_columns {
+            Err(format!("column index {} is out of bounds for spreadsheet with {} column(s)",idx,n_columns))?
+        }
+        Ok(self.extract_column_from_idx(idx))
+    }
 
-        let idx1 = column_index(&self.column_headers, col1)?;
-        let idx2 = column_index(&self.column_headers, col2)?;
-    
-        // Extract and parse columns as f64
-        let col1 = self.data.slice(s![.., idx1]).map(|x| x.parse::<f64>().unwrap_or(f64::NAN));
-        let col2 = self.data.slice(s![.., idx2]).map(|x| x.parse::<f64>().unwrap_or(f64::NAN));
-    
-        // Perform division 
-        let new_col = Self::do_operation(col1.as_slice().unwrap(),col2.as_slice().unwrap(),operation)?;
+    /// returns the indices of every column whose header matches `pattern`, failing if there are none
+    pub fn column_indices(&self,pattern:&str) -> Result<Vec<usize>,SheetError> {
+        let re = header_regex(pattern, self.case_insensitive)?;
+        let indices:Vec<usize> = self.column_headers.iter().enumerate().filter(|(_,header)| re.is_match(header)).map(|(idx,_)| idx).collect();
+        if indices.is_empty() {
+            Err(SheetError::NoColumnMatch(pattern.to_string()))?
+        }
+        Ok(indices)
+    }
 
-        // Convert result to strings
-        let new_col_str: Vec<_> = new_col.iter().map(|&x| x.to_string()).collect();
-    
-        let n_rows = self.data.shape()[0];
+    /// picks the `n`th (0-based, in column order) of the headers matching `pattern`, for patterns that are
+    /// known to be ambiguous. Returns the column index together with the header that was selected.
+    pub fn column_index_nth(&self,pattern:&str,n:usize) -> Result<(usize,&str),SheetError> {
+        let indices = self.column_indices(pattern)?;
+        match indices.get(n) {
+            Some(&idx) => Ok((idx,self.column_headers[idx].as_str())),
+            None => Err(format!("match {} requested for pattern '{}' but only {} header(s) match",n,pattern,indices.len()))?
+        }
+    }
 
-        let to_append = Array2::from_shape_vec((n_rows, 1), new_col_str).unwrap();
-    
-        //println!("to append shape: {:?}",to_append.shape());
-        //println!("data shape: {:?}",self.data.shape());
-    
-       // Stack the new column with the original data
-        self.data.append(Axis(1), to_append.view()).unwrap();
+    /// returns the index of the column whose header is exactly `literal`. Unlike the pattern based lookups,
+    /// no part of `literal` is interpreted as a regular expression.
+    pub fn column_index_exact(&self,literal:&str) -> Result<usize,SheetError> {
+        let matches:Vec<_> = self.column_headers.iter().enumerate().filter(|(_,header)| header.as_str() == literal).collect();
+        if matches.len() > 1 {
+            Err(SheetError::AmbiguousColumn { pattern: literal.to_string(), matches: matches.iter().map(|(_,header)| header.to_string()).collect() })?
+        }else if matches.is_empty() {
+            Err(SheetError::NoColumnMatch(literal.to_string()))?
+        }else {
+            Ok(matches[0].0)
+        }
+    }
 
-        self.column_headers.push(new_col_name.to_string());
+    fn extract_column_from_idx(&self,column_idx:usize) -> Vec<f32> {
+        self.data.slice(s![.., column_idx]).map(|x| self.numeric_options.parse(x) as f32).to_vec()
+    }
 
+    /// applies `operation` element-wise to two columns and appends the result as a new column. Arithmetic
+    /// operations (see `OPERATIONS`) produce numbers, comparisons (see `COMPARISONS`) produce `true`/`false`.
+    pub fn column_op(&mut self,col1:&str,operation:&str,col2:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let new_col = self.binary_op_strings(col1, operation, col2)?;
+        self.append_column(new_col, new_col_name);
         Ok(())
     }
 
-    pub fn column_headers(&self) -> &[String] {
-        &self.column_headers
+    /// same as `column_op`, but the second operand is the column matching `col_other` in `other`, aligned by
+    /// row. Both spreadsheets must have the same number of rows. Cells of `other` are parsed with its own
+    /// numeric options.
+    pub fn column_op_cross(&mut self,col_self:&str,operation:&str,other:&SpreadSheet2D,col_other:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let idx_self = self.resolve_column(col_self)?;
+        let idx_other = other.resolve_column(col_other)?;
+        let (n_rows,other_rows) = (self.n_rows(),other.n_rows());
+        if n_rows != other_rows {
+            Err(format!("cannot combine columns of spreadsheets with different row counts ({} and {})",n_rows,other_rows))?
+        }
+        let col1 = self.parse_column(idx_self);
+        let col2 = other.parse_column(idx_other);
+        let new_col = self.binary_op_strings_from_values(&col1, operation, &col2)?;
+        self.append_column(new_col, new_col_name);
+        Ok(())
     }
 
-}
+    /// applies `operation` between every column matching `pattern` and the single column matching `operand`,
+    /// appending one result column per match. Result headers are built from `name_template` by replacing
+    /// `{}` with the matched header, e.g. `"{}_norm"`.
+    pub fn column_op_broadcast(&mut self,pattern:&str,operation:&str,operand:&str,name_template:&str) -> Result<(),SheetError> {
+        let indices = self.column_indices(pattern)?;
+        let operand_idx = self.resolve_column(operand)?;
+        let mut results = vec![];
+        for idx in indices {
+            let new_col = self.binary_op_strings_from_idx(idx, operation, operand_idx)?;
+            results.push((new_col,name_template.replace("{}", &self.column_headers[idx])));
+        }
+        for (new_col,new_col_name) in results {
+            self.append_column(new_col, &new_col_name);
+        }
+        Ok(())
+    }
+
+    /// same as `column_op`, but if a header exactly equal to `new_col_name` already exists its values are
+    /// overwritten in place instead of appending a duplicate column
+    pub fn column_op_overwrite(&mut self,col1:&str,operation:&str,col2:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let new_col = self.binary_op_strings(col1, operation, col2)?;
+        self.overwrite_column(new_col, new_col_name)
+    }
+
+    /// computes a binary operation between two columns and stringifies the result
+    fn binary_op_strings(&self,col1:&str,operation:&str,col2:&str) -> Result<Vec<String>,SheetError> {
+
+        let idx1 = self.resolve_column(col1)?;
+        let idx2 = self.resolve_column(col2)?;
+        self.binary_op_strings_from_idx(idx1, operation, idx2)
+    }
+
+    fn binary_op_strings_from_idx(&self,idx1:usize,operation:&str,idx2:usize) -> Result<Vec<String>,SheetError> {
+        match self.precision {
+            Precision::F64 => self.binary_op_strings_from_values(&self.parse_column(idx1), operation, &self.parse_column(idx2)),
+            Precision::F32 => self.binary_op_strings_from_values(&self.parse_column_as::<f32>(idx1), operation, &self.parse_column_as::<f32>(idx2)),
+        }
+    }
+
+    fn binary_op_strings_from_values<F:Compute>(&self,col1:&[F],operation:&str,col2:&[F]) -> Result<Vec<String>,SheetError> {
+        if COMPARISONS.contains(&operation) {
+            let new_col = Self::do_comparison(col1,col2,operation)?;
+            return Ok(new_col.iter().map(|x| x.to_string()).collect())
+        }
+    
+        let new_col = Self::do_operation(col1,col2,operation)?;
+
+        Ok(self.format_values(&new_col))
+    }
+
+    /// stringifies results computed with either precision
+    fn format_values<F:Compute>(&self,values:&[F]) -> Vec<String> {
+        values.iter().map(|&x| self.format_value(x.to_output())).collect()
+    }
+
+    /// evaluates an arithmetic expression such as `(col_a + col_b) / col_c` and appends the result as a new
+    /// column. Column names are resolved with the same pattern matching as `column_op`, and numeric literals
+    /// are broadcast against the columns they are combined with.
+    pub fn column_expr(&mut self,expression:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let parsed = expr::parse(expression)?;
+        let new_col = match self.precision {
+            Precision::F64 => self.format_values(&self.eval_expr::<f64>(&parsed)?),
+            Precision::F32 => self.format_values(&self.eval_expr::<f32>(&parsed)?),
+        };
+        self.append_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// appends `weights[0] * cols[0] + weights[1] * cols[1] + ...`, with each column resolved by pattern
+    /// matching. Cells that fail to parse make the sum NaN for that row.
+    pub fn weighted_sum(&mut self,cols:&[&str],weights:&[f64],new_col_name:&str) -> Result<(),SheetError> {
+        if cols.is_empty() {
+            Err("weighted_sum needs at least one column")?
+        }
+        if cols.len() != weights.len() {
+            Err(format!("expected one weight per column, found {} column(s) and {} weight(s)",cols.len(),weights.len()))?
+        }
+        let columns:Vec<Vec<f64>> = cols.iter().map(|col|{
+            self.resolve_column(col).map(|idx| self.parse_column(idx))
+        }).collect::<Result<_,_>>()?;
+        let n_rows = self.data.shape()[0];
+        let new_col:Vec<f64> = (0..n_rows).into_par_iter().map(|row|{
+            columns.iter().zip(weights).map(|(column,weight)| weight * column[row]).sum()
+        }).collect();
+        self.append_numeric_column(&new_col, new_col_name);
+        Ok(())
+    }
+
+    /// appends `stat` computed per row over the columns matched by `cols`, e.g. the mean of several sensor
+    /// columns. Each pattern must resolve to a single column. NaN and unparsable cells are skipped.
+    pub fn row_reduce(&mut self,cols:&[&str],stat:Stat,new_col_name:&str) -> Result<(),SheetError> {
+        if cols.is_empty() {
+            Err("row_reduce needs at least one column")?
+        }
+        let columns:Vec<Vec<f64>> = cols.iter().map(|col|{
+            self.resolve_column(col).map(|idx| self.parse_column(idx))
+        }).collect::<Result<_,_>>()?;
+        let n_rows = self.data.shape()[0];
+        let new_col:Vec<f64> = (0..n_rows).into_par_iter().map(|row|{
+            let values:Vec<f64> = columns.iter().map(|column| column[row]).filter(|x| !x.is_nan()).collect();
+            stat.compute(&values)
+        }).collect();
+        self.append_numeric_column(&new_col, new_col_name);
+        Ok(())
+    }
+
+    fn eval_expr<F:Compute>(&self,expression:&expr::Expr) -> Result<Vec<F>,SheetError> {
+        let n_rows = self.data.shape()[0];
+        match expression {
+            expr::Expr::Column(pattern) => {
+                let idx = self.resolve_column(pattern)?;
+                Ok(self.parse_column_as(idx))
+            }
+            expr::Expr::Number(value) => Ok(vec![F::from(*value).unwrap_or(F::nan());n_rows]),
+            expr::Expr::Neg(operand) => Ok(self.eval_expr::<F>(operand)?.par_iter().map(|&x| -x).collect()),
+            expr::Expr::Binary(op,lhs,rhs) => {
+                let lhs = self.eval_expr(lhs)?;
+                let rhs = self.eval_expr(rhs)?;
+                Self::do_operation(&lhs,&rhs,&op.to_string())
+            }
+        }
+    }
+
+    /// applies a single operand operation (see `UNARY_OPERATIONS`) to the matched column and appends the
+    /// result as a new column
+    pub fn column_unary_op(&mut self,col:&str,operation:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let new_col = match self.precision {
+            Precision::F64 => self.format_values(&Self::do_unary_operation(&self.parse_column(idx),operation)?),
+            Precision::F32 => self.format_values(&Self::do_unary_operation(&self.parse_column_as::<f32>(idx),operation)?),
+        };
+        self.append_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// broadcasts a scalar against every element of the matched column. If `scalar_on_left` is true
+    /// the scalar is used as the left operand (`scalar - column`), otherwise as the right operand
+    /// (`column - scalar`). NaN entries in the source column remain NaN in the result.
+    pub fn column_scalar_op(&mut self,col:&str,operation:&str,scalar:f64,scalar_on_left:bool,new_col_name:&str) -> Result<(),SheetError> {
+
+        let idx = self.resolve_column(col)?;
+
+        let new_col = match self.precision {
+            Precision::F64 => self.scalar_op_strings(&self.parse_column(idx), operation, scalar, scalar_on_left)?,
+            Precision::F32 => self.scalar_op_strings(&self.parse_column_as::<f32>(idx), operation, scalar as f32, scalar_on_left)?,
+        };
+
+        self.append_column(new_col, new_col_name);
+
+        Ok(())
+    }
+
+    fn scalar_op_strings<F:Compute>(&self,column:&[F],operation:&str,scalar:F,scalar_on_left:bool) -> Result<Vec<String>,SheetError> {
+        let scalars = vec![scalar;column.len()];
+        let new_col = if scalar_on_left {
+            Self::do_operation(&scalars,column,operation)?
+        }else {
+            Self::do_operation(column,&scalars,operation)?
+        };
+        Ok(self.format_values(&new_col))
+    }
+
+    /// element-wise single operand operations
+    fn do_unary_operation<F:Float + Send + Sync>(col:&[F],operation:&str) -> Result<Vec<F>,SheetError> {
+        match operation {
+            "neg" => Ok(col.par_iter().map(|&a| -a).collect()),
+            "recip" => Ok(col.par_iter().map(|&a| a.recip()).collect()),
+            "abs" => Ok(col.par_iter().map(|&a| a.abs()).collect()),
+            "sqrt" => Ok(col.par_iter().map(|&a| a.sqrt()).collect()),
+            "log10" => Ok(col.par_iter().map(|&a| a.log10()).collect()),
+            "ln" => Ok(col.par_iter().map(|&a| a.ln()).collect()),
+            "exp" => Ok(col.par_iter().map(|&a| a.exp()).collect()),
+            _=> Err(SheetError::UnknownOperation(operation.to_string()))?
+        }
+    }
+
+    /// element-wise comparisons. Following IEEE 754, any comparison involving NaN is false, including `==`.
+    fn do_comparison<F:Float + Send + Sync>(col1:&[F],col2:&[F],operation:&str) -> Result<Vec<bool>,SheetError> {
+        match operation {
+            ">" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a > b).collect()),
+            "<" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a < b).collect()),
+            ">=" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a >= b).collect()),
+            "<=" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a <= b).collect()),
+            "==" => Ok(col1.par_iter().zip(col2.par_iter()).map(|(&a, &b)| a == b).collect()),
+            _=> Err(SheetError::UnknownOperation(operation.to_string()))?
+        }
+    }
+
+    /// applies `f` to every element of the matched column in parallel and appends the result as a new column.
+    /// Cells that fail to parse are passed to `f` as NaN, so NaN propagation is the caller's responsibility
+    /// inside the closure.
+    pub fn map_column<F>(&mut self,col:&str,new_col_name:&str,f:F) -> Result<(),SheetError>
+    where F: Fn(f64) -> f64 + Sync + Send {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let new_col:Vec<f64> = column.par_iter().map(|&x| f(x)).collect();
+        self.append_numeric_column(&new_col, new_col_name);
+        Ok(())
+    }
+
+    /// appends a column holding `if_true` in the rows where `predicate` returns true for the matched column
+    /// and `if_false` elsewhere, like IF() in a spreadsheet. Cells that fail to parse are passed to
+    /// `predicate` as NaN.
+    pub fn when(&mut self,col:&str,predicate:impl Fn(f64) -> bool,if_true:&str,if_false:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let new_col = self.parse_column(idx).into_iter().map(|x|{
+            if predicate(x) { if_true.to_string() } else { if_false.to_string() }
+        }).collect();
+        self.append_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// same as `when` with the condition `column <comparison> threshold`, where `comparison` is one of
+    /// `COMPARISONS`. Like all comparisons, the condition is false for cells that fail to parse.
+    pub fn when_compare(&mut self,col:&str,comparison:&str,threshold:f64,if_true:&str,if_false:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let conditions = Self::do_comparison(&column, &vec![threshold;column.len()], comparison)?;
+        let new_col = conditions.into_iter().map(|condition|{
+            if condition { if_true.to_string() } else { if_false.to_string() }
+        }).collect();
+        self.append_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// replaces every match of the regex `pattern` in the cells of the matched column with `replacement`,
+    /// in place on the raw strings. `replacement` may refer to capture groups as `$1` or `${name}`. Useful to
+    /// clean up a column before running arithmetic on it, e.g. stripping a `$` prefix.
+    pub fn replace_in_column(&mut self,col:&str,pattern:&str,replacement:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let re = Regex::new(pattern)?;
+        for cell in self.data.column_mut(idx) {
+            if let Cow::Owned(replaced) = re.replace_all(cell, replacement) {
+                *cell = replaced;
+            }
+        }
+        Ok(())
+    }
+
+    /// keeps only the rows where `predicate` returns true for the matched column, returning the number
+    /// of rows removed. Cells that fail to parse are passed to `predicate` as NaN, so they are dropped
+    /// unless the predicate explicitly accepts NaN.
+    pub fn filter_rows(&mut self,col:&str,predicate:impl Fn(f64) -> bool) -> Result<usize,SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let keep:Vec<usize> = column.iter().enumerate().filter(|(_,&x)| predicate(x)).map(|(i,_)| i).collect();
+        let n_removed = column.len() - keep.len();
+        self.data = self.data.select(Axis(0), &keep);
+        Ok(n_removed)
+    }
+
+    /// removes rows that repeat an earlier row, returning the number of rows removed. With `subset`, rows
+    /// count as duplicates if the cells of the matched columns are equal, otherwise every cell must be equal.
+    /// Cells are compared as strings and the first occurrence is kept, in the original row order.
+    pub fn dedup(&mut self,subset:Option<&[&str]>) -> Result<usize,SheetError> {
+        let key_columns:Vec<usize> = match subset {
+            Some(patterns) => patterns.iter().map(|pattern| self.resolve_column(pattern)).collect::<Result<_,_>>()?,
+            None => (0..self.n_columns()).collect(),
+        };
+        let mut seen:HashSet<Vec<&str>> = HashSet::new();
+        let keep:Vec<usize> = (0..self.n_rows()).filter(|&row|{
+            seen.insert(key_columns.iter().map(|&idx| self.data[[row, idx]].as_str()).collect())
+        }).collect();
+        let n_removed = self.n_rows() - keep.len();
+        self.data = self.data.select(Axis(0), &keep);
+        Ok(n_removed)
+    }
+
+    /// reorders the rows by the values of the matched column. With `numeric` the cells are compared as f64,
+    /// with cells that fail to parse (NaN) sorted to the end regardless of direction. Otherwise cells are
+    /// compared lexicographically. The sort is stable and every column is reordered consistently.
+    pub fn sort_by_column(&mut self,col:&str,ascending:bool,numeric:bool) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let mut permutation:Vec<usize> = (0..self.data.shape()[0]).collect();
+        if numeric {
+            let column = self.parse_column(idx);
+            permutation.sort_by(|&a,&b|{
+                let (x,y) = (column[a],column[b]);
+                match (x.is_nan(),y.is_nan()) {
+                    (true,true) => Ordering::Equal,
+                    (true,false) => Ordering::Greater,
+                    (false,true) => Ordering::Less,
+                    _ => if ascending { x.total_cmp(&y) } else { y.total_cmp(&x) }
+                }
+            });
+        }else {
+            let column = self.data.column(idx);
+            permutation.sort_by(|&a,&b|{
+                if ascending { column[a].cmp(&column[b]) } else { column[b].cmp(&column[a]) }
+            });
+        }
+        self.data = self.data.select(Axis(0), &permutation);
+        Ok(())
+    }
+
+    /// reduces the matched column to a single statistic, skipping entries that fail to parse
+    pub fn column_stat(&self,col:&str,stat:Stat) -> Result<f64,SheetError> {
+        Ok(self.column_stat_detailed(col, stat)?.0)
+    }
+
+    /// same as `column_stat`, but also returns the number of NaN entries that were skipped
+    pub fn column_stat_detailed(&self,col:&str,stat:Stat) -> Result<(f64,usize),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let values:Vec<f64> = column.iter().cloned().filter(|x| !x.is_nan()).collect();
+        let n_skipped = column.len() - values.len();
+        Ok((stat.compute(&values),n_skipped))
+    }
+
+    /// counts the values of the matched column in `bins` equal-width bins spanning its minimum to maximum,
+    /// returning `(bin_lo, bin_hi, count)` per bin. Bins include their lower edge and exclude their upper
+    /// edge, except the last bin which includes the maximum. NaN, infinite and unparsable cells are skipped.
+    /// If every value is the same, all of them are counted in the first bin and every edge equals the value.
+    pub fn histogram(&self,col:&str,bins:usize) -> Result<Vec<(f64,f64,usize)>,SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let (lo,width) = self.bin_layout(&column, bins)?;
+        let mut counts = vec![0;bins];
+        for &x in column.iter().filter(|x| x.is_finite()) {
+            counts[bin_index(x, lo, width, bins)] += 1;
+        }
+        Ok(counts.into_iter().enumerate().map(|(i,count)|{
+            (lo + i as f64 * width,lo + (i + 1) as f64 * width,count)
+        }).collect())
+    }
+
+    /// appends a column labelling each row with the bin of `histogram` its value falls in, e.g. `[0, 2.5)`
+    /// or `[7.5, 10]` for the last bin, so the rows can be grouped by bin. Rows that `histogram` skips get an
+    /// empty label.
+    pub fn bin_column(&mut self,col:&str,bins:usize,new_col_name:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let (lo,width) = self.bin_layout(&column, bins)?;
+        let labels:Vec<String> = (0..bins).map(|i|{
+            let closing = if i + 1 == bins { ']' } else { ')' };
+            format!("[{}, {}{}",self.format_value(lo + i as f64 * width),self.format_value(lo + (i + 1) as f64 * width),closing)
+        }).collect();
+        let new_col = column.iter().map(|&x|{
+            if x.is_finite() { labels[bin_index(x, lo, width, bins)].clone() } else { String::new() }
+        }).collect();
+        self.append_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// the lower edge and width of `bins` equal-width bins spanning the finite values of `column`
+    fn bin_layout(&self,column:&[f64],bins:usize) -> Result<(f64,f64),SheetError> {
+        if bins == 0 {
+            Err("the number of bins must be at least 1")?
+        }
+        let values:Vec<f64> = column.iter().cloned().filter(|x| x.is_finite()).collect();
+        if values.is_empty() {
+            Err("cannot bin a column without finite numeric values")?
+        }
+        let lo = Stat::Min.compute(&values);
+        let hi = Stat::Max.compute(&values);
+        Ok((lo,(hi - lo) / bins as f64))
+    }
+
+    /// the `q` quantile of the matched column for `q` in [0,1], skipping entries that fail to parse. Values
+    /// between ranks are linearly interpolated (type 7, the default in R and NumPy). NaN if nothing parses.
+    pub fn quantile(&self,col:&str,q:f64) -> Result<f64,SheetError> {
+        Ok(self.quantiles(col, &[q])?[0])
+    }
+
+    /// same as `quantile` for several values of `q`, sorting the column only once
+    pub fn quantiles(&self,col:&str,qs:&[f64]) -> Result<Vec<f64>,SheetError> {
+        if let Some(q) = qs.iter().find(|q| !(0. ..=1.).contains(*q)) {
+            Err(format!("quantile must be between 0 and 1, got {}",q))?
+        }
+        let idx = self.resolve_column(col)?;
+        let mut values:Vec<f64> = self.parse_column(idx).into_iter().filter(|x| !x.is_nan()).collect();
+        values.sort_by(|a,b| a.total_cmp(b));
+        Ok(qs.iter().map(|&q|{
+            if values.is_empty() {
+                return f64::NAN
+            }
+            let rank = q * (values.len() - 1) as f64;
+            let lower = rank.floor() as usize;
+            let upper = rank.ceil() as usize;
+            values[lower] + (values[upper] - values[lower]) * (rank - lower as f64)
+        }).collect())
+    }
+
+    /// the inferred type of every column, paired with its header, in column order. Numbers are read with the
+    /// separators of the spreadsheet's `NumericOptions`.
+    pub fn infer_schema(&self) -> Vec<(String,ColumnType)> {
+        self.column_headers.iter().zip(self.data.columns()).map(|(header,column)|{
+            (header.clone(),ColumnType::infer(column.iter(), &self.numeric_options))
+        }).collect()
+    }
+
+    /// number of distinct raw string values in the matched column
+    pub fn n_unique(&self,col:&str) -> Result<usize,SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.data.column(idx);
+        let unique:HashSet<&String> = column.iter().collect();
+        Ok(unique.len())
+    }
+
+    /// each distinct raw string value of the matched column with the number of times it occurs, sorted by
+    /// descending count. Values with the same count keep the order in which they first appear.
+    pub fn value_counts(&self,col:&str) -> Result<Vec<(String,usize)>,SheetError> {
+        let idx = self.resolve_column(col)?;
+        let mut counts:Vec<(String,usize)> = vec![];
+        let column = self.data.column(idx);
+        let mut positions:HashMap<&String,usize> = HashMap::new();
+        for value in column.iter() {
+            match positions.get(value) {
+                Some(&pos) => counts[pos].1 += 1,
+                None => {
+                    positions.insert(value, counts.len());
+                    counts.push((value.clone(),1));
+                }
+            }
+        }
+        // stable sort keeps ties in order of first appearance
+        counts.sort_by_key(|&(_,count)| std::cmp::Reverse(count));
+        Ok(counts)
+    }
+
+    /// rounds the matched column to integers in place, written without a decimal point regardless of the
+    /// number format. Cells that fail to parse are written as the NaN token.
+    pub fn round_column(&mut self,col:&str,mode:RoundMode) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let rounded:Vec<String> = self.parse_column(idx).iter().map(|&x|{
+            if x.is_nan() {
+                self.nan_token.clone()
+            }else {
+                // adding zero turns -0 into 0
+                (mode.apply(x) + 0.).to_string()
+            }
+        }).collect();
+        self.data.column_mut(idx).iter_mut().zip(rounded).for_each(|(cell,value)| *cell = value);
+        Ok(())
+    }
+
+    /// normalizes the matched column using statistics computed over the entries that parse, overwriting it in
+    /// place if `new_col_name` is `None` and appending the result otherwise. Cells that fail to parse stay NaN.
+    /// A constant column has no spread to divide by and normalizes to all zeros with either method, while a
+    /// z-score of fewer than two values is NaN.
+    pub fn normalize_column(&mut self,col:&str,method:NormMethod,new_col_name:Option<&str>) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let values:Vec<f64> = column.iter().cloned().filter(|x| !x.is_nan()).collect();
+        let (offset,scale) = match method {
+            NormMethod::MinMax => {
+                let min = Stat::Min.compute(&values);
+                (min,Stat::Max.compute(&values) - min)
+            }
+            NormMethod::ZScore => (Stat::Mean.compute(&values),Stat::Std.compute(&values)),
+        };
+        let new_col:Vec<f64> = column.par_iter().map(|&x|{
+            if scale == 0. && !x.is_nan() {
+                0.
+            }else {
+                (x - offset) / scale
+            }
+        }).collect();
+        self.replace_or_append(idx, &new_col, new_col_name);
+        Ok(())
+    }
+
+    /// limits every value of the matched column to [lo, hi], overwriting it in place if `new_col_name` is
+    /// `None` and appending the result otherwise. NaN values pass through unchanged.
+    pub fn clamp_column(&mut self,col:&str,lo:f64,hi:f64,new_col_name:Option<&str>) -> Result<(),SheetError> {
+        // NaN bounds would make f64::clamp panic
+        if lo.is_nan() || hi.is_nan() {
+            Err(format!("clamp bounds must be numbers, got {} and {}",lo,hi))?
+        }
+        if lo > hi {
+            Err(format!("lower bound {} is greater than upper bound {}",lo,hi))?
+        }
+        let idx = self.resolve_column(col)?;
+        let new_col:Vec<f64> = self.parse_column(idx).par_iter().map(|x| x.clamp(lo, hi)).collect();
+        self.replace_or_append(idx, &new_col, new_col_name);
+        Ok(())
+    }
+
+    /// clamps the matched column in place to the range between its `lower_q` and `upper_q` quantiles,
+    /// given as fractions in [0,1] as for `quantile`, e.g. 0.05 and 0.95
+    pub fn winsorize(&mut self,col:&str,lower_q:f64,upper_q:f64) -> Result<(),SheetError> {
+        let bounds = self.quantiles(col, &[lower_q,upper_q])?;
+        if bounds.iter().any(|x| x.is_nan()) {
+            return Ok(())
+        }
+        self.clamp_column(col, bounds[0], bounds[1], None)
+    }
+
+    /// overwrites the column at `idx` with `values` if `new_col_name` is `None`, otherwise appends them
+    fn replace_or_append(&mut self,idx:usize,values:&[f64],new_col_name:Option<&str>) {
+        match new_col_name {
+            Some(name) => self.append_numeric_column(values, name),
+            None => {
+                let new_col_str:Vec<String> = values.iter().map(|&x| self.format_value(x)).collect();
+                self.data.column_mut(idx).iter_mut().zip(new_col_str).for_each(|(cell,value)| *cell = value);
+            }
+        }
+    }
+
+    /// groups the rows by the string value of the key column and reduces the value column within each group,
+    /// skipping entries that fail to parse. Returns a new two column spreadsheet of the unique keys and their
+    /// aggregated values, with groups in the order their key first appears. The output settings and the
+    /// preamble are carried over.
+    pub fn group_by(&self,key_col:&str,value_col:&str,agg:Stat) -> Result<SpreadSheet2D,SheetError> {
+        let key_idx = self.resolve_column(key_col)?;
+        let value_idx = self.resolve_column(value_col)?;
+
+        let key_column = self.data.column(key_idx);
+        let mut keys:Vec<&String> = vec![];
+        let mut groups:HashMap<&String,Vec<f64>> = HashMap::new();
+        for (key,value) in key_column.iter().zip(self.parse_column(value_idx)) {
+            let group = groups.entry(key).or_insert_with(||{
+                keys.push(key);
+                vec![]
+            });
+            if !value.is_nan() {
+                group.push(value);
+            }
+        }
+
+        let cells:Vec<String> = keys.iter().flat_map(|&key|{
+            [key.clone(),self.format_value(agg.compute(&groups[key]))]
+        }).collect();
+        let data = Array2::from_shape_vec((keys.len(), 2), cells).unwrap();
+        let column_headers = vec![self.column_headers[key_idx].clone(),self.column_headers[value_idx].clone()];
+
+        Ok(self.with_data(column_headers, data))
+    }
+
+    /// reshapes long data into a wide table with one row per distinct value of `index_col` and one column per
+    /// distinct value of `columns_col`, each cell holding `agg` over the matching entries of `values_col`.
+    /// Rows and columns are in the order their values first appear, and the first column keeps the header of
+    /// `index_col`. Values that fail to parse are skipped like in `group_by`. Combinations of index and
+    /// category that never occur are left empty, while combinations whose values all failed to parse hold
+    /// the aggregate of no values (NaN for most statistics, 0 for `Stat::Count` and `Stat::Sum`).
+    pub fn pivot(&self,index_col:&str,columns_col:&str,values_col:&str,agg:Stat) -> Result<SpreadSheet2D,SheetError> {
+        let index_idx = self.resolve_column(index_col)?;
+        let category_idx = self.resolve_column(columns_col)?;
+        let value_idx = self.resolve_column(values_col)?;
+
+        let index_column = self.data.column(index_idx);
+        let category_column = self.data.column(category_idx);
+        // the Vecs keep first-seen order, the maps find the position of a value without scanning them
+        let mut keys:Vec<&String> = vec![];
+        let mut key_positions:HashMap<&String,usize> = HashMap::new();
+        let mut categories:Vec<&String> = vec![];
+        let mut category_positions:HashMap<&String,usize> = HashMap::new();
+        let mut cells:HashMap<(usize,usize),Vec<f64>> = HashMap::new();
+        for ((key,category),value) in index_column.iter().zip(category_column.iter()).zip(self.parse_column(value_idx)) {
+            let row = *key_positions.entry(key).or_insert_with(||{
+                keys.push(key);
+                keys.len() - 1
+            });
+            let col = *category_positions.entry(category).or_insert_with(||{
+                categories.push(category);
+                categories.len() - 1
+            });
+            let values = cells.entry((row,col)).or_default();
+            if !value.is_nan() {
+                values.push(value);
+            }
+        }
+
+        let data:Vec<String> = keys.iter().enumerate().flat_map(|(row,&key)|{
+            let cells = &cells;
+            let values = (0..categories.len()).map(move |col|{
+                cells.get(&(row,col)).map(|values| self.format_value(agg.compute(values))).unwrap_or_default()
+            });
+            std::iter::once(key.clone()).chain(values)
+        }).collect();
+        let data = Array2::from_shape_vec((keys.len(), categories.len() + 1), data)?;
+        let column_headers = std::iter::once(self.column_headers[index_idx].clone())
+            .chain(categories.iter().map(|category| category.to_string()))
+            .collect();
+
+        Ok(self.with_data(column_headers, data))
+    }
+
+    /// reshapes wide data into long data, the inverse of `pivot`. Each column matched by `value_cols` is
+    /// stacked below the previous one, with the matched `id_cols` repeated alongside, a `var_name` column
+    /// holding the header the value came from and a `value_name` column holding the value. The result has
+    /// `n_rows * value_cols.len()` rows. Cells are copied verbatim.
+    pub fn melt(&self,id_cols:&[&str],value_cols:&[&str],var_name:&str,value_name:&str) -> Result<SpreadSheet2D,SheetError> {
+        if value_cols.is_empty() {
+            Err("melt needs at least one value column")?
+        }
+        let id_indices:Vec<usize> = id_cols.iter().map(|col| self.resolve_column(col)).collect::<Result<_,_>>()?;
+        let value_indices:Vec<usize> = value_cols.iter().map(|col| self.resolve_column(col)).collect::<Result<_,_>>()?;
+        if let Some(&idx) = value_indices.iter().find(|idx| id_indices.contains(idx)) {
+            Err(format!("column '{}' can't be both an id and a value column",self.column_headers[idx]))?
+        }
+
+        let mut column_headers:Vec<String> = id_indices.iter().map(|&idx| self.column_headers[idx].clone()).collect();
+        for name in [var_name,value_name] {
+            if column_headers.iter().any(|header| header == name) {
+                Err(format!("melt would produce two columns named '{}'",name))?
+            }
+            column_headers.push(name.to_string());
+        }
+
+        let n_rows = self.n_rows();
+        let mut cells = Vec::with_capacity(n_rows * value_indices.len() * column_headers.len());
+        for &value_idx in &value_indices {
+            for row in 0..n_rows {
+                cells.extend(id_indices.iter().map(|&idx| self.data[[row, idx]].clone()));
+                cells.push(self.column_headers[value_idx].clone());
+                cells.push(self.data[[row, value_idx]].clone());
+            }
+        }
+        let data = Array2::from_shape_vec((n_rows * value_indices.len(), column_headers.len()), cells)?;
+
+        Ok(self.with_data(column_headers, data))
+    }
+
+    /// a new spreadsheet with the given headers and data that keeps the preamble and all settings of this one
+    fn with_data(&self,column_headers:Vec<String>,data:Array2<String>) -> Self {
+        Self {
+            preamble: self.preamble.clone(),
+            col_delimeter: self.col_delimeter.clone(),
+            output_delimeter: self.output_delimeter.clone(),
+            quoting: self.quoting,
+            write_header: self.write_header,
+            data,
+            column_headers,
+            number_format: self.number_format,
+            nan_token: self.nan_token.clone(),
+            case_insensitive: self.case_insensitive,
+            numeric_options: self.numeric_options.clone(),
+            line_ending: self.line_ending,
+            write_bom: self.write_bom,
+            quote_all: self.quote_all,
+            precision: self.precision,
+        }
+    }
+
+    fn parse_column_strict(&self,column_idx:usize) -> Result<Vec<f64>,SheetError> {
+        self.data.column(column_idx).iter().enumerate().map(|(row,x)|{
+            self.numeric_options.try_parse(x).ok_or_else(|| SheetError::InvalidNumber {
+                row,
+                column: self.column_headers[column_idx].clone(),
+                value: x.to_string(),
+            })
+        }).collect()
+    }
+
+    fn parse_column(&self,column_idx:usize) -> Vec<f64> {
+        self.data.slice(s![.., column_idx]).iter().map(|x| self.numeric_options.parse(x)).collect()
+    }
+
+    /// same as `parse_column`, converting each cell to `F` as it is parsed
+    fn parse_column_as<F:Compute>(&self,column_idx:usize) -> Vec<F> {
+        self.data.slice(s![.., column_idx]).iter().map(|x| F::from(self.numeric_options.parse(x)).unwrap_or(F::nan())).collect()
+    }
+
+    /// sets the number of decimal places used when writing the results of operations. Original string data
+    /// is not affected and is always written back verbatim.
+    pub fn set_output_precision(&mut self,decimals:usize) {
+        self.number_format = NumberFormat::Fixed(decimals);
+    }
+
+    /// sets how the results of operations are written. Original string data is not affected.
+    pub fn set_number_format(&mut self,format:NumberFormat) {
+        self.number_format = format;
+    }
+
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    /// sets the options used whenever cells are parsed to numbers
+    pub fn set_numeric_options(&mut self,options:NumericOptions) {
+        self.numeric_options = options;
+    }
+
+    /// sets the value that missing cells parse to. See `NumericOptions::na_fill`.
+    pub fn set_na_fill(&mut self,fill:Option<f64>) {
+        self.numeric_options.na_fill = fill;
+    }
+
+    /// sets the string written wherever the result of an operation is NaN, e.g. "" or "NA". Defaults to "NaN".
+    /// Original string data is not affected.
+    pub fn set_nan_token(&mut self,token:&str) {
+        self.nan_token = token.to_string();
+    }
+
+    fn format_value(&self,x:f64) -> String {
+        if x.is_nan() {
+            return self.nan_token.clone()
+        }
+        self.number_format.format(x)
+    }
+
+    fn append_numeric_column(&mut self,values:&[f64],new_col_name:&str) {
+        // Convert result to strings
+        let new_col_str: Vec<_> = values.iter().map(|&x| self.format_value(x)).collect();
+        self.append_column(new_col_str, new_col_name);
+    }
 
-fn column_index(column_header: &[String], pattern: &str) -> Result<usize,Box<dyn Error>> {
-    let re = Regex::new(pattern)?;
+    fn append_column(&mut self,values:Vec<String>,new_col_name:&str) {
+    
+        let n_rows = self.data.shape()[0];
+
+        let to_append = Array2::from_shape_vec((n_rows, 1), values).unwrap();
+    
+       // Stack the new column with the original data
+        self.data.append(Axis(1), to_append.view()).unwrap();
+
+        self.column_headers.push(new_col_name.to_string());
+    }
+
+    /// inserts a column of `values` so that it ends up at index `at`, shifting the columns from `at` onwards
+    /// to the right. `at` may equal the number of columns to append. A column named `header` must not
+    /// already exist and there must be one value per data row.
+    pub fn insert_column(&mut self,at:usize,header:&str,values:Vec<String>) -> Result<(),SheetError> {
+        let (n_rows,n_columns) = (self.n_rows(),self.n_columns());
+        if at > n_columns {
+            Err(format!("cannot insert column at index {} of a spreadsheet with {} column(s)",at,n_columns))?
+        }
+        if values.len() != n_rows {
+            Err(format!("expected {} value(s) for column '{}', found {}",n_rows,header,values.len()))?
+        }
+        if self.column_headers.iter().any(|existing| existing == header) {
+            Err(format!("a column named '{}' already exists",header))?
+        }
+        let column = Array2::from_shape_vec((n_rows, 1), values)?;
+        self.data = ndarray::concatenate![Axis(1), self.data.slice(s![.., ..at]), column, self.data.slice(s![.., at..])];
+        self.column_headers.insert(at, header.to_string());
+        Ok(())
+    }
+
+    /// moves the column at index `from` so that it ends up at index `to`, shifting the columns in between.
+    /// Useful to place a computed column, which is always appended, in front of its source columns.
+    pub fn move_column(&mut self,from:usize,to:usize) -> Result<(),SheetError> {
+        let n_columns = self.n_columns();
+        if from >= n_columns || to >= n_columns {
+            Err(format!("cannot move column {} to index {} of a spreadsheet with {} column(s)",from,to,n_columns))?
+        }
+        if from == to {
+            return Ok(())
+        }
+        let mut order:Vec<usize> = (0..n_columns).filter(|&idx| idx != from).collect();
+        order.insert(to, from);
+        self.data = self.data.select(Axis(1), &order);
+        let header = self.column_headers.remove(from);
+        self.column_headers.insert(to, header);
+        Ok(())
+    }
+
+    /// same as `column_op`, but the result column is inserted at index `at` instead of appended
+    pub fn column_op_at(&mut self,col1:&str,operation:&str,col2:&str,new_col_name:&str,at:usize) -> Result<(),SheetError> {
+        let new_col = self.binary_op_strings(col1, operation, col2)?;
+        if at > self.n_columns() {
+            Err(format!("cannot insert column at index {} of a spreadsheet with {} column(s)",at,self.n_columns()))?
+        }
+        self.append_column(new_col, new_col_name);
+        self.move_column(self.n_columns() - 1, at)
+    }
+
+    /// splits every cell of the matched column on `sep` and appends the parts as one column per header in
+    /// `new_headers`, e.g. `"lat,lon"` into `lat` and `lon`. The original column is kept. Fails listing the
+    /// 0-based data rows that don't split into exactly `new_headers.len()` parts, leaving the spreadsheet
+    /// unchanged.
+    pub fn split_column(&mut self,col:&str,sep:&str,new_headers:&[&str]) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        if sep.is_empty() {
+            Err("cannot split on an empty separator")?
+        }
+        if new_headers.is_empty() {
+            Err("at least one header is required for the split columns")?
+        }
+        let column = self.data.column(idx);
+        let mut parts = vec![vec![];new_headers.len()];
+        let mut mismatched = vec![];
+        for (row,cell) in column.iter().enumerate() {
+            let fields:Vec<&str> = cell.split(sep).collect();
+            if fields.len() != new_headers.len() {
+                mismatched.push(format!("row {} ('{}' has {} part(s))",row,cell,fields.len()));
+                continue
+            }
+            for (values,field) in parts.iter_mut().zip(fields) {
+                values.push(field.to_string());
+            }
+        }
+        if !mismatched.is_empty() {
+            Err(format!("column '{}' doesn't split on '{}' into {} part(s) at: {}",self.column_headers[idx],sep,new_headers.len(),mismatched.join(", ")))?
+        }
+        for (values,header) in parts.into_iter().zip(new_headers) {
+            self.append_column(values, header);
+        }
+        Ok(())
+    }
+
+    /// appends a column holding `value` in every row, e.g. to tag rows with their source before `vstack`.
+    /// A column named `header` must not already exist.
+    pub fn add_constant_column(&mut self,header:&str,value:&str) -> Result<(),SheetError> {
+        self.insert_column(self.n_columns(), header, vec![value.to_string();self.n_rows()])
+    }
+
+    /// appends a column numbering the data rows 0, 1, 2, ... or, if `one_based` is set, 1, 2, 3, ... A column
+    /// named `header` must not already exist.
+    pub fn add_index_column(&mut self,header:&str,one_based:bool) -> Result<(),SheetError> {
+        let start = usize::from(one_based);
+        let values = (start..start + self.n_rows()).map(|i| i.to_string()).collect();
+        self.insert_column(self.n_columns(), header, values)
+    }
+
+    /// overwrites the values of the column whose header is exactly `col_name`, appending a new column
+    /// if no such header exists
+    fn overwrite_column(&mut self,values:Vec<String>,col_name:&str) -> Result<(),SheetError> {
+        if !self.column_headers.iter().any(|header| header == col_name) {
+            self.append_column(values, col_name);
+            return Ok(())
+        }
+        let idx = self.column_index_exact(col_name)?;
+        self.data.column_mut(idx).iter_mut().zip(values).for_each(|(cell,value)| *cell = value);
+        Ok(())
+    }
+
+    /// removes the matched column from the spreadsheet
+    pub fn drop_column(&mut self,col:&str) -> Result<(),SheetError> {
+        self.drop_columns(&[col])
+    }
+
+    /// removes all of the matched columns. Every pattern is resolved before anything is removed, so the
+    /// spreadsheet is left unchanged if any pattern fails to match.
+    pub fn drop_columns(&mut self,patterns:&[&str]) -> Result<(),SheetError> {
+        let mut to_drop = vec![];
+        for pattern in patterns {
+            to_drop.push(self.resolve_column(pattern)?);
+        }
+        let keep:Vec<usize> = (0..self.column_headers.len()).filter(|idx| !to_drop.contains(idx)).collect();
+        if keep.is_empty() {
+            Err("cannot drop the last remaining column(s)")?
+        }
+        self.data = self.data.select(Axis(1), &keep);
+        self.column_headers = keep.iter().map(|&idx| self.column_headers[idx].clone()).collect();
+        Ok(())
+    }
+
+    /// rearranges the columns in the order of the header patterns in `order`. Columns not matched by any
+    /// pattern are dropped.
+    pub fn reorder_columns(&mut self,order:&[&str]) -> Result<(),SheetError> {
+        self.reorder_columns_with(order, false)
+    }
+
+    /// same as `reorder_columns`, but columns not matched by any pattern are appended after the listed ones
+    /// in their current order if `append_unlisted` is true
+    pub fn reorder_columns_with(&mut self,order:&[&str],append_unlisted:bool) -> Result<(),SheetError> {
+        let mut permutation = vec![];
+        for pattern in order {
+            let idx = self.resolve_column(pattern)?;
+            if permutation.contains(&idx) {
+                Err(format!("column '{}' is listed more than once (pattern '{}')",self.column_headers[idx],pattern))?
+            }
+            permutation.push(idx);
+        }
+        if append_unlisted {
+            let unlisted:Vec<usize> = (0..self.column_headers.len()).filter(|idx| !permutation.contains(idx)).collect();
+            permutation.extend(unlisted);
+        }
+        if permutation.is_empty() {
+            Err("cannot reorder to zero columns")?
+        }
+        self.data = self.data.select(Axis(1), &permutation);
+        self.column_headers = permutation.iter().map(|&idx| self.column_headers[idx].clone()).collect();
+        Ok(())
+    }
+
+    /// renames the matched column. The rename is rejected if another column already has the header `new_name`.
+    pub fn rename_column(&mut self,col:&str,new_name:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        if self.column_headers.iter().enumerate().any(|(i,header)| i != idx && header == new_name) {
+            Err(format!("cannot rename column '{}' to '{}': a column with that name already exists",self.column_headers[idx],new_name))?
+        }
+        self.column_headers[idx] = new_name.to_string();
+        Ok(())
+    }
+
+    /// appends all columns of `other` to the right of this spreadsheet, aligned by row index. Headers of
+    /// `other` that collide with existing headers are suffixed with `_2`, `_3`, ... so they stay unique.
+    /// The preamble of this spreadsheet is kept and the preamble of `other` is ignored.
+    pub fn hstack(&mut self,other:&SpreadSheet2D) -> Result<(),SheetError> {
+        let n_rows = self.data.shape()[0];
+        let other_rows = other.data.shape()[0];
+        if n_rows != other_rows {
+            Err(format!("cannot hstack spreadsheets with different row counts ({} and {})",n_rows,other_rows))?
+        }
+        self.data.append(Axis(1), other.data.view())?;
+        for header in &other.column_headers {
+            let new_header = unique_header(&self.column_headers, header);
+            self.column_headers.push(new_header);
+        }
+        Ok(())
+    }
+
+    /// combines the rows of this spreadsheet with the rows of `other` whose `right_key` cell equals the
+    /// `left_key` cell, comparing the raw strings. The result holds every column of this spreadsheet followed
+    /// by the columns of `other` except its key, with colliding headers suffixed like in `hstack`. A left row
+    /// matching several right rows is repeated once per match, in the order of `other`. With `JoinKind::Left`,
+    /// left rows without a match are kept once with empty cells for the columns of `other`, which parse as
+    /// missing values. Rows are in the order of this spreadsheet, whose preamble and settings are kept.
+    pub fn join(&self,other:&SpreadSheet2D,left_key:&str,right_key:&str,how:JoinKind) -> Result<SpreadSheet2D,SheetError> {
+        let left_idx = self.resolve_column(left_key)?;
+        let right_idx = other.resolve_column(right_key)?;
+
+        let right_key_column = other.data.column(right_idx);
+        let mut right_rows:HashMap<&String,Vec<usize>> = HashMap::new();
+        for (row,key) in right_key_column.iter().enumerate() {
+            right_rows.entry(key).or_default().push(row);
+        }
+        let right_columns:Vec<usize> = (0..other.n_columns()).filter(|&idx| idx != right_idx).collect();
+
+        let mut column_headers = self.column_headers.clone();
+        for &idx in &right_columns {
+            let new_header = unique_header(&column_headers, &other.column_headers[idx]);
+            column_headers.push(new_header);
+        }
+
+        let mut cells = vec![];
+        let mut n_rows = 0;
+        for (row,left_row) in self.data.axis_iter(Axis(0)).enumerate() {
+            match right_rows.get(&self.data[[row, left_idx]]) {
+                Some(matches) => {
+                    for &right_row in matches {
+                        cells.extend(left_row.iter().cloned());
+                        cells.extend(right_columns.iter().map(|&idx| other.data[[right_row, idx]].clone()));
+                        n_rows += 1;
+                    }
+                }
+                None if how == JoinKind::Left => {
+                    cells.extend(left_row.iter().cloned());
+                    cells.extend(right_columns.iter().map(|_| String::new()));
+                    n_rows += 1;
+                }
+                None => {}
+            }
+        }
+        let data = Array2::from_shape_vec((n_rows, column_headers.len()), cells)?;
+
+        Ok(self.with_data(column_headers, data))
+    }
+
+    /// appends the rows of `other` after the rows of this spreadsheet. Both spreadsheets must have the same
+    /// headers in the same order. The preamble of this spreadsheet is kept and the preamble of `other` is ignored.
+    pub fn vstack(&mut self,other:&SpreadSheet2D) -> Result<(),SheetError> {
+        if self.column_headers != other.column_headers {
+            let n = self.column_headers.len().max(other.column_headers.len());
+            let differences:Vec<String> = (0..n).filter_map(|i|{
+                let a = self.column_headers.get(i).map(|x|x.as_str()).unwrap_or("<missing>");
+                let b = other.column_headers.get(i).map(|x|x.as_str()).unwrap_or("<missing>");
+                if a != b {
+                    Some(format!("col {}: '{}' vs '{}'",i + 1,a,b))
+                }else {
+                    None
+                }
+            }).collect();
+            Err(format!("cannot vstack spreadsheets with different headers: {}",differences.join(", ")))?
+        }
+        self.data.append(Axis(0), other.data.view())?;
+        Ok(())
+    }
+
+    /// flips rows and columns. The first column is treated as row labels: its header stays the first header
+    /// and its values become the remaining headers. The other headers become the first column of the new
+    /// data, so every old column turns into a row. Duplicate values in the first column produce duplicate
+    /// headers. The preamble is kept unchanged. Fails on a spreadsheet without columns.
+    pub fn transpose(&mut self) -> Result<(),SheetError> {
+        let Some(first_header) = self.column_headers.first() else {
+            Err("cannot transpose a spreadsheet without columns")?
+        };
+        let mut column_headers = vec![first_header.clone()];
+        column_headers.extend(self.data.column(0).iter().cloned());
+
+        let labels = Array2::from_shape_vec((self.column_headers.len() - 1, 1), self.column_headers[1..].to_vec())?;
+        let values = self.data.slice(s![.., 1..]).reversed_axes();
+        self.data = ndarray::concatenate(Axis(1), &[labels.view(), values])?;
+        self.column_headers = column_headers;
+        Ok(())
+    }
+
+    /// a copy holding only the first `n` data rows, or all of them if there are fewer. Headers, preamble and
+    /// settings are kept.
+    pub fn head(&self,n:usize) -> SpreadSheet2D {
+        let n = n.min(self.n_rows());
+        self.with_data(self.column_headers.clone(), self.data.slice(s![..n, ..]).to_owned())
+    }
+
+    /// a copy holding only the last `n` data rows, or all of them if there are fewer. Headers, preamble and
+    /// settings are kept.
+    pub fn tail(&self,n:usize) -> SpreadSheet2D {
+        let start = self.n_rows().saturating_sub(n);
+        self.with_data(self.column_headers.clone(), self.data.slice(s![start.., ..]).to_owned())
+    }
+
+    /// a copy holding only the columns matched by `patterns`, in the order of `patterns`. Each pattern must
+    /// match exactly one column and no column may be selected twice. The preamble and settings are kept.
+    pub fn select(&self,patterns:&[&str]) -> Result<SpreadSheet2D,SheetError> {
+        if patterns.is_empty() {
+            Err("cannot select zero columns")?
+        }
+        let mut indices = vec![];
+        for pattern in patterns {
+            let idx = self.resolve_column(pattern)?;
+            if indices.contains(&idx) {
+                Err(format!("column '{}' is selected more than once (pattern '{}')",self.column_headers[idx],pattern))?
+            }
+            indices.push(idx);
+        }
+        let column_headers = indices.iter().map(|&idx| self.column_headers[idx].clone()).collect();
+        Ok(self.with_data(column_headers, self.data.select(Axis(1), &indices)))
+    }
+
+    /// returns `(n_rows, n_columns)` of the data, not counting the header or preamble
+    pub fn shape(&self) -> (usize,usize) {
+        let shape = self.data.shape();
+        (shape[0],shape[1])
+    }
+
+    pub fn n_rows(&self) -> usize {
+        self.shape().0
+    }
+
+    pub fn n_columns(&self) -> usize {
+        self.shape().1
+    }
+
+    /// returns the cell at the 0-based `row` and `col`, or None if either is out of bounds
+    pub fn get(&self,row:usize,col:usize) -> Option<&str> {
+        self.data.get((row,col)).map(|x| x.as_str())
+    }
+
+    /// overwrites the cell at the 0-based `row` and `col`
+    pub fn set(&mut self,row:usize,col:usize,value:String) -> Result<(),SheetError> {
+        let (n_rows,n_columns) = self.shape();
+        match self.data.get_mut((row,col)) {
+            Some(cell) => {
+                *cell = value;
+                Ok(())
+            }
+            None => Err(format!("cell ({}, {}) is out of bounds for spreadsheet of shape ({}, {})",row,col,n_rows,n_columns))?
+        }
+    }
+
+    /// overwrites the cell at the 0-based `row` in the column matching `col_pattern`
+    pub fn set_by_header(&mut self,row:usize,col_pattern:&str,value:String) -> Result<(),SheetError> {
+        let col = self.resolve_column(col_pattern)?;
+        self.set(row, col, value)
+    }
+
+    pub fn column_headers(&self) -> &[String] {
+        &self.column_headers
+    }
+
+    /// makes every header pattern match regardless of case, for all operations resolving columns by
+    /// pattern. Note that more headers may match a pattern, so lookups that were unique can become ambiguous.
+    pub fn set_case_insensitive(&mut self,case_insensitive:bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    /// resolves a header pattern with the spreadsheet's case sensitivity
+    fn resolve_column(&self,pattern:&str) -> Result<usize,SheetError> {
+        column_index_with_case(&self.column_headers, pattern, self.case_insensitive)
+    }
+
+}
+
+/// the bin of `x` for bins starting at `lo`, with the maximum counted in the last bin
+fn bin_index(x:f64,lo:f64,width:f64,bins:usize) -> usize {
+    if width == 0. {
+        return 0
+    }
+    (((x - lo) / width) as usize).min(bins - 1)
+}
+
+/// `header`, or `header` suffixed with `_2`, `_3`, ... if it is already one of `headers`
+fn unique_header(headers:&[String],header:&str) -> String {
+    let mut new_header = header.to_string();
+    let mut suffix = 2;
+    while headers.contains(&new_header) {
+        new_header = format!("{}_{}",header,suffix);
+        suffix += 1;
+    }
+    new_header
+}
+
+/// returns the index of the single header matching the regex `pattern`, failing if none or several match.
+/// `SheetError::AmbiguousColumn` lists every matching header.
+pub fn column_index(column_header: &[String], pattern: &str) -> Result<usize,SheetError> {
+    column_index_with_case(column_header, pattern, false)
+}
+
+/// same as `column_index`, ignoring case if `case_insensitive` is set. Ignoring case can make a pattern
+/// match more headers, so a pattern that is unique when matching case may become ambiguous.
+pub fn column_index_with_case(column_header: &[String], pattern: &str, case_insensitive: bool) -> Result<usize,SheetError> {
+    let re = header_regex(pattern, case_insensitive)?;
     let matches:Vec<_> = column_header.iter().enumerate().filter_map(|(idx,header)|{
         if re.is_match(header) {
             Some((idx,header))
@@ -160,15 +2108,469 @@ fn column_index(column_header: &[String], pattern: &str) -> Result<usize,Box<dyn
         }
     }).collect();
     if matches.len() > 1 {
-        println!("too many matches found for pattern: '{}'",pattern);
-        println!("matches found:");
-        for m in matches {
-            println!("col: {} : {}",m.0 + 1,m.1);
-        }
-        Err("consider narrowing your search pattern")?
+        Err(SheetError::AmbiguousColumn { pattern: pattern.to_string(), matches: matches.iter().map(|(_,header)| header.to_string()).collect() })?
     }else if matches.is_empty() {
-        Err(format!("no matches found for '{}'",pattern))?
+        Err(SheetError::NoColumnMatch(pattern.to_string()))?
     }else {
         Ok(matches[0].0)
     }
-}
\ No newline at end of file
+}
+
+fn header_regex(pattern:&str,case_insensitive:bool) -> Result<Regex,SheetError> {
+    Ok(RegexBuilder::new(pattern).case_insensitive(case_insensitive).build()?)
+}
+
+/// delimiters tried by `detect_delimiter`, in order of preference
+pub const DELIMITER_CANDIDATES:[&str;4] = ["\t",",",";","|"];
+
+/// number of lines of the sample inspected by `detect_delimiter`
+const DELIMITER_SAMPLE_LINES:usize = 10;
+
+/// guesses the column delimiter of `sample`, usually the start of a file without its preamble. A candidate
+/// from `DELIMITER_CANDIDATES` is accepted if it splits every one of the first few non-empty lines into the
+/// same number of columns, and more than one. Returns `None` if no candidate or more than one qualifies.
+pub fn detect_delimiter(sample:&str) -> Option<String> {
+    let lines:Vec<&str> = sample.lines().filter(|line| !line.trim().is_empty()).take(DELIMITER_SAMPLE_LINES).collect();
+    if lines.is_empty() {
+        return None
+    }
+    let consistent:Vec<&str> = DELIMITER_CANDIDATES.iter().copied().filter(|delim|{
+        let counts:Result<Vec<usize>,String> = lines.iter().map(|line| split_quoted(line, delim).map(|fields| fields.len())).collect();
+        match counts {
+            Ok(counts) => counts[0] > 1 && counts.iter().all(|&n| n == counts[0]),
+            Err(_) => false
+        }
+    }).collect();
+    match consistent[..] {
+        [delim] => Some(delim.to_string()),
+        _ => None
+    }
+}
+
+/// parses an expression as accepted by `SpreadSheet2D::column_expr` and returns the column patterns it references
+pub fn expression_columns(expression:&str) -> Result<Vec<String>,SheetError> {
+    let parsed = expr::parse(expression)?;
+    Ok(parsed.columns().into_iter().map(|x|x.to_string()).collect())
+}
+
+/// decides which leading lines belong to the preamble
+pub(crate) struct PreambleMatcher {
+    line_offset:usize,
+    pattern:Option<Regex>,
+}
+
+impl PreambleMatcher {
+    pub(crate) fn new(opts:&ParseOptions) -> Result<Self,SheetError> {
+        let pattern = match &opts.preamble_pattern {
+            Some(pattern) => Some(Regex::new(pattern)?),
+            None => None
+        };
+        Ok(Self { line_offset: opts.line_offset, pattern })
+    }
+
+    /// whether `line` continues the preamble, given the number of preamble lines found so far
+    pub(crate) fn matches(&self,n_preamble:usize,line:&str) -> bool {
+        n_preamble < self.line_offset || self.pattern.as_ref().is_some_and(|re| re.is_match(line))
+    }
+}
+
+/// headers `col_1`, `col_2`, ... used for input without a header row
+fn synthesize_headers(n_columns:usize) -> Vec<String> {
+    (1..=n_columns).map(|i| format!("col_{}",i)).collect()
+}
+
+/// prefix of a `col_delimeter` that is a regex rather than a literal delimiter
+const REGEX_DELIMITER_PREFIX:&str = "regex:";
+
+/// splits lines into fields as configured by the parse options, with a regex delimiter compiled once
+pub(crate) struct LineSplitter<'a> {
+    opts:&'a ParseOptions,
+    regex:Option<Regex>,
+}
+
+impl<'a> LineSplitter<'a> {
+    pub(crate) fn new(opts:&'a ParseOptions) -> Result<Self,SheetError> {
+        let regex = match opts.col_delimeter.strip_prefix(REGEX_DELIMITER_PREFIX) {
+            Some(pattern) if !opts.whitespace_delimited => {
+                if opts.quoting {
+                    Err("quoting is not supported with a regex delimiter")?
+                }
+                let regex = Regex::new(pattern)?;
+                if regex.is_match("") {
+                    Err(format!("regex delimiter '{}' matches the empty string",pattern))?
+                }
+                Some(regex)
+            }
+            _ => None
+        };
+        Ok(Self { opts, regex })
+    }
+
+    /// the delimiter to write the spreadsheet back out with. A regex can't be used to join fields, so
+    /// input split on a regex is written tab delimited.
+    fn output_delimiter(&self) -> &str {
+        if self.regex.is_some() { "\t" } else { &self.opts.col_delimeter }
+    }
+
+    /// splits a line into fields on the configured delimiter
+    pub(crate) fn split(&self,line:&str) -> Result<Vec<String>,String> {
+        let mut fields = vec![];
+        self.split_into(line, &mut fields)?;
+        Ok(fields)
+    }
+
+    /// splits a line into fields on the configured delimiter, appending them to `fields`
+    fn split_into(&self,line:&str,fields:&mut Vec<String>) -> Result<(),String> {
+        let opts = self.opts;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let n_before = fields.len();
+        if opts.whitespace_delimited {
+            fields.extend(line.split_whitespace().map(|x|x.to_string()));
+        }else if let Some(regex) = &self.regex {
+            fields.extend(regex.split(line).map(|x|x.to_string()));
+        }else if opts.quoting {
+            fields.extend(split_quoted(line, &opts.col_delimeter)?);
+        }else {
+            fields.extend(line.split(opts.col_delimeter.as_str()).map(|x|x.to_string()));
+        }
+        if opts.trim {
+            for field in &mut fields[n_before..] {
+                let trimmed = field.trim();
+                if trimmed.len() != field.len() {
+                    *field = trimmed.to_string();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// cuts a line into trimmed fields of `widths` characters, or `None` if the line is too short
+fn split_fixed_width(line:&str,widths:&[usize]) -> Option<Vec<String>> {
+    let mut fields = Vec::with_capacity(widths.len());
+    let mut chars = line.chars();
+    for &width in widths {
+        let field:String = chars.by_ref().take(width).collect();
+        if field.chars().count() < width {
+            return None
+        }
+        fields.push(field.trim().to_string());
+    }
+    Some(fields)
+}
+
+/// splits a line into fields following RFC 4180 quoting rules
+fn split_quoted(line:&str,col_delimeter:&str) -> Result<Vec<String>,String> {
+    let mut fields = vec![];
+    let mut rest = line;
+    loop {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut field = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i,'"')) => {
+                        if quoted[i+1..].starts_with('"') {
+                            field.push('"');
+                            chars.next();
+                        }else {
+                            break i + 1
+                        }
+                    }
+                    Some((_,c)) => field.push(c),
+                    None => Err(format!("unterminated quoted field in column {}",fields.len() + 1))?
+                }
+            };
+            fields.push(field);
+            rest = &quoted[end..];
+            if rest.is_empty() {
+                break
+            }
+            match rest.strip_prefix(col_delimeter) {
+                Some(r) => rest = r,
+                None => Err(format!("unexpected character after closing quote in column {}",fields.len()))?
+            }
+        }else {
+            match rest.split_once(col_delimeter) {
+                Some((field,r)) => {
+                    fields.push(field.to_string());
+                    rest = r;
+                }
+                None => {
+                    fields.push(rest.to_string());
+                    break
+                }
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// builds a `SpreadSheet2D` programmatically without going through a delimited string
+#[derive(Debug,Clone)]
+pub struct SpreadSheet2DBuilder {
+    preamble:Vec<String>,
+    col_delimeter:String,
+    column_headers:Vec<String>,
+    rows:Vec<Vec<String>>,
+}
+
+impl Default for SpreadSheet2DBuilder {
+    fn default() -> Self {
+        Self { preamble: vec![], col_delimeter: "\t".to_string(), column_headers: vec![], rows: vec![] }
+    }
+}
+
+impl SpreadSheet2DBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn column_headers(mut self,headers:Vec<String>) -> Self {
+        self.column_headers = headers;
+        self
+    }
+
+    pub fn col_delimeter(mut self,col_delimeter:&str) -> Self {
+        self.col_delimeter = col_delimeter.to_string();
+        self
+    }
+
+    pub fn preamble(mut self,preamble:Vec<String>) -> Self {
+        self.preamble = preamble;
+        self
+    }
+
+    pub fn push_row(mut self,row:Vec<String>) -> Self {
+        self.rows.push(row);
+        self
+    }
+
+    /// builds the spreadsheet, checking that every row has as many entries as there are headers
+    pub fn build(self) -> Result<SpreadSheet2D,SheetError> {
+        let n_columns = self.column_headers.len();
+        if n_columns == 0 {
+            Err("spreadsheet must have at least one column header")?
+        }
+        for (row_idx,row) in self.rows.iter().enumerate() {
+            if row.len() != n_columns {
+                Err(format!("row {} has {} element(s), expected {}",row_idx,row.len(),n_columns))?
+            }
+        }
+        let n_rows = self.rows.len();
+        let data = Array2::from_shape_vec((n_rows,n_columns),self.rows.into_iter().flatten().collect())?;
+        Ok(SpreadSheet2D::from_parts(self.preamble, &self.col_delimeter, self.column_headers, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(s:&str) -> SpreadSheet2D {
+        SpreadSheet2D::try_from_string(s.to_string(), "\t", 0).unwrap()
+    }
+
+    #[test]
+    fn unknown_operation_names_the_operation() {
+        let mut s = sheet("a\tb\n1\t2\n");
+        match s.column_op("a", "pow", "b", "c") {
+            Err(SheetError::UnknownOperation(operation)) => assert_eq!(operation, "pow"),
+            other => panic!("expected an unknown operation, got {:?}",other.err()),
+        }
+        let e = s.column_unary_op("a", "cube", "c").err().unwrap();
+        assert!(e.to_string().starts_with("unknown operation cube. Supported operations are: + - *"));
+    }
+
+    #[test]
+    fn clamp_rejects_nan_bounds() {
+        let mut s = sheet("a\n1\n5\n");
+        assert!(s.clamp_column("a", f64::NAN, 1., None).is_err());
+        assert!(s.clamp_column("a", 0., f64::NAN, None).is_err());
+        assert!(s.clamp_column("a", 2., 1., None).is_err());
+        s.clamp_column("a", 0., 2., None).unwrap();
+        assert_eq!(s.get(1,0), Some("2"));
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let mut s = sheet("name\tx\ty\np\t1\t2\nq\t3\t4\n");
+        s.transpose().unwrap();
+        assert_eq!(s.to_string(), "name\tp\tq\nx\t1\t3\ny\t2\t4\n");
+    }
+
+    #[test]
+    fn transpose_without_columns_fails() {
+        let mut s = SpreadSheet2D::from_parts(vec![], "\t", vec![], Array2::from_shape_vec((0,0), vec![]).unwrap());
+        assert!(s.transpose().is_err());
+    }
+
+    #[test]
+    fn blank_whitespace_header_is_rejected() {
+        let e = SpreadSheet2D::from_whitespace_delimited("   \n".to_string(), 0).err().unwrap();
+        assert_eq!(e.to_string(), "spreadsheet must have at least one column header");
+    }
+
+    #[test]
+    fn from_numeric_array_requires_columns() {
+        let e = SpreadSheet2D::from_numeric_array(Array2::zeros((2,0)), vec![], "\t").err().unwrap();
+        assert_eq!(e.to_string(), "spreadsheet must have at least one column header");
+        let s = SpreadSheet2D::from_numeric_array(Array2::from_elem((1,1), 2.5), vec!["a".to_string()], "\t").unwrap();
+        assert_eq!(s.to_string(), "a\n2.5\n");
+    }
+
+    #[test]
+    fn crlf_line_endings_are_stripped_from_cells() {
+        let opts = ParseOptions { col_delimeter: ",".to_string(), ..Default::default() };
+        let splitter = LineSplitter::new(&opts).unwrap();
+        let mut fields = vec![];
+        splitter.split_into("1,2\r", &mut fields).unwrap();
+        assert_eq!(fields, vec!["1","2"]);
+
+        for input in ["a,b\r\n1,2\r\n","a,b\r\n1,2\r"] {
+            let s = SpreadSheet2D::try_from_string(input.to_string(), ",", 0).unwrap();
+            assert_eq!(s.column_headers(), &["a","b"]);
+            assert_eq!(s.get(0,1), Some("2"));
+        }
+    }
+
+    #[test]
+    fn european_number_format() {
+        let opts = NumericOptions { thousands_sep: Some('.'), decimal_sep: Some(','), ..Default::default() };
+        assert_eq!(opts.try_parse("1.234,56"), Some(1234.56));
+        assert_eq!(opts.try_parse("-0,5"), Some(-0.5));
+        assert_eq!(opts.try_parse("abc"), None);
+
+        let default = NumericOptions::default();
+        assert_eq!(default.try_parse("1,234"), None);
+        assert_eq!(default.try_parse("1234.5"), Some(1234.5));
+    }
+
+    #[test]
+    fn trim_strips_headers_and_cells() {
+        let opts = ParseOptions { col_delimeter: ",".to_string(), trim: true, ..Default::default() };
+        let s = SpreadSheet2D::from_string_with_options(" a , b \n 3.5 ,x\n".to_string(), &opts).unwrap();
+        assert_eq!(s.column_headers(), &["a","b"]);
+        assert_eq!(s.get(0,0), Some("3.5"));
+        assert_eq!(s.column_numeric_strict("^a$").unwrap(), vec![3.5]);
+    }
+
+    #[test]
+    fn bom_is_stripped_and_written_back() {
+        let s = sheet("\u{FEFF}a\tb\n1\t2\n");
+        assert_eq!(column_index(s.column_headers(), "^a$").ok(), Some(0));
+        assert!(s.write_bom());
+        assert_eq!(s.to_string(), "\u{FEFF}a\tb\n1\t2\n");
+    }
+
+    #[test]
+    fn header_only_file() {
+        let s = SpreadSheet2D::try_from_string("a\tb\n".to_string(), "\t", 0).unwrap();
+        assert_eq!(s.shape(), (0,2));
+        assert_eq!(s.to_string(), "a\tb\n");
+    }
+
+    #[test]
+    fn parse_reports_first_bad_row() {
+        let n = 1000;
+        let mut input = String::from("a\tb\n");
+        for i in 0..n {
+            input.push_str(&format!("{}\t{}\n",i,i * 2));
+        }
+        let s = sheet(&input);
+        assert_eq!(s.shape(), (n,2));
+        assert_eq!(s.get(n - 1,1), Some((2 * (n - 1)).to_string().as_str()));
+
+        input.push_str("bad\n");
+        match SpreadSheet2D::try_from_string(input, "\t", 0) {
+            Err(SheetError::RowLengthMismatch { line, expected: 2, got: 1 }) => assert_eq!(line, n + 2),
+            other => panic!("expected a row length mismatch, got {:?}",other.err()),
+        }
+    }
+
+    #[test]
+    fn weighted_sum_needs_columns() {
+        let mut s = sheet("a\tb\n1\t2\n");
+        assert!(s.weighted_sum(&[], &[], "w").is_err());
+        s.weighted_sum(&["^a$","^b$"], &[2.,0.5], "w").unwrap();
+        assert_eq!(s.get(0,2), Some("3"));
+    }
+
+    #[test]
+    fn non_finite_cells_are_not_numbers() {
+        let opts = NumericOptions::default();
+        assert_eq!(Number::parse("3", &opts), Some(Number::Integer(3)));
+        assert_eq!(Number::parse("2.5", &opts), Some(Number::Float(2.5)));
+        assert_eq!(Number::parse("NaN", &opts), None);
+        assert_eq!(Number::parse("inf", &opts), None);
+        let s = sheet("a\tb\n1\t1\nNaN\t2.5\n");
+        assert_eq!(s.infer_schema(), vec![("a".to_string(),ColumnType::String),("b".to_string(),ColumnType::Float)]);
+    }
+
+    #[test]
+    fn number_detection_uses_numeric_options() {
+        let opts = NumericOptions { decimal_sep: Some(','), ..Default::default() };
+        assert_eq!(Number::parse("1,5", &opts), Some(Number::Float(1.5)));
+        assert_eq!(Number::parse("1,5", &NumericOptions::default()), None);
+
+        let mut s = sheet("a\tb\n1,5\t2\n\t3\n");
+        s.set_numeric_options(opts);
+        assert_eq!(s.columns_numeric()[0][0], 1.5);
+        assert_eq!(s.infer_schema()[0].1, ColumnType::Float);
+        assert_eq!(s.infer_schema()[1].1, ColumnType::Integer);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_numbers_follow_number_parse() {
+        // a column with a cell that isn't a number is a string column, as in to_parquet
+        let s = sheet("a\tb\tc\n1\t1\ttrue\nNaN\t2.5\tFALSE\n");
+        let json:serde_json::Value = serde_json::from_str(&s.to_json().unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!([{"a":"1","b":1.0,"c":true},{"a":"NaN","b":2.5,"c":false}]));
+
+        let mut s = sheet("a\n1,5\n");
+        s.set_numeric_options(NumericOptions { decimal_sep: Some(','), ..Default::default() });
+        let json:serde_json::Value = serde_json::from_str(&s.to_json().unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!([{"a":1.5}]));
+
+        let s = sheet("a\tb\n1\tx\n\t\n");
+        assert_eq!(s.infer_schema()[0].1, ColumnType::Integer);
+        let json:serde_json::Value = serde_json::from_str(&s.to_json().unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!([{"a":1,"b":"x"},{"a":null,"b":null}]));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_types_follow_infer_schema() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        let s = sheet("i\tf\tb\ts\n1\t1.5\ttrue\tx\n\t\tFALSE\t\n");
+        let path = std::env::temp_dir().join(format!("sheet_calc_test_{}.parquet",std::process::id()));
+        s.to_parquet(&path).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap().build().unwrap();
+        let batches:Vec<_> = reader.collect::<Result<_,_>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let schema = batches[0].schema();
+        let types:Vec<_> = schema.fields().iter().map(|field| field.data_type().clone()).collect();
+        assert_eq!(types, vec![arrow_schema::DataType::Int64,arrow_schema::DataType::Float64,arrow_schema::DataType::Boolean,arrow_schema::DataType::Utf8]);
+        for col in [0,1,3] {
+            assert_eq!(batches[0].column(col).null_count(), 1);
+        }
+    }
+
+    #[test]
+    fn f32_numeric_view() {
+        let mut s = sheet("a\tb\n0.1\t0.2\n");
+        let mut view = s.numeric_view_as::<f32>();
+        assert_eq!(view.column("^a$").unwrap()[0], 0.1f32);
+        view.column_op("^a$", "+", "^b$", "c").unwrap();
+        view.commit(&mut s).unwrap();
+        assert_eq!(s.get(0,2), Some("0.3"));
+    }
+
+    #[test]
+    fn pivot_keeps_first_seen_order() {
+        let s = sheet("k\tc\tv\nb\tx\t1\na\ty\t2\nb\ty\t3\nb\tx\t5\n");
+        let p = s.pivot("^k$", "^c$", "^v$", Stat::Sum).unwrap();
+        assert_eq!(p.to_string(), "k\tx\ty\nb\t6\t3\na\t\t2\n");
+    }
+}
@@ -1,40 +1,211 @@
 use std::error::Error;
-use std::io::Write;
-use std::path::PathBuf;
-use std::{fs::File, io::Read};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::{fs::{self, File}, io::Read};
 use clap::Parser;
-use ndarray::{Array2, Axis};
-use ndarray::s;
+#[cfg(feature = "gzip")]
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 use regex::Regex;
-use rayon::prelude::*;
 use serde::{Serialize,Deserialize};
-use sheet_calc::SpreadSheet2D;
-use toml;
+use sheet_calc::{column_index_with_case, detect_delimiter, expression_columns, process_streaming, read_header, LineEnding, NumberFormat, NumericOptions, ParseOptions, SheetError, SpreadSheet2D, SpreadSheet2DBuilder, Stat, COMPARISONS, OPERATIONS, UNARY_OPERATIONS};
 
 #[derive(clap::Parser, Debug)]
-struct Args {
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command:Option<Command>,
+    /// without a subcommand the arguments of `run` are accepted directly
+    #[command(flatten)]
+    run:RunArgs,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// run the calculations of a config on the input. This is the default without a subcommand
+    Run(RunArgs),
+    /// print count, mean, standard deviation, min, median and max of every column
+    Stats(StatsArgs),
+    /// print the first rows of the input
+    Head(HeadArgs),
+    /// rewrite the input with a different delimiter, line ending or byte order mark
+    Convert(ConvertArgs),
+    /// write a template calculation config
+    GenConfig(GenConfigArgs),
+}
+
+// where the input is read from and how
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// input file, or - to read from stdin. Can be repeated to stack several files with the same columns
     #[clap(short, long, default_value = "input.txt")]
-    input: PathBuf,
+    input: Vec<PathBuf>,
+    /// guess the column delimiter from the start of the input if the config doesn't set column_delimeter
+    #[clap(long)]
+    auto_delimiter:bool,
+    /// report progress on stderr while reading the input bytes and when each calculation starts. Parsing the
+    /// input into a spreadsheet shows no progress
+    #[cfg(feature = "progress")]
+    #[clap(long)]
+    progress:bool,
+    /// worksheet to read from .xlsx inputs. Defaults to the first one
+    #[cfg(feature = "xlsx")]
+    #[clap(long, value_name = "NAME")]
+    sheet:Option<String>,
+}
+
+impl InputArgs {
+    /// whether --progress was given. Always false without the progress feature
+    fn progress(&self) -> bool {
+        #[cfg(feature = "progress")]
+        return self.progress;
+        #[cfg(not(feature = "progress"))]
+        false
+    }
+}
+
+// where the output is written and how
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    /// output file, or - to write to stdout
     #[clap(short, long, default_value = "output.txt")]
     output: PathBuf,
+    /// end output lines with CRLF instead of LF
+    #[clap(long)]
+    crlf:bool,
+    /// whether the output starts with a UTF-8 byte order mark. By default a BOM is written if the input had one
+    #[clap(long, value_name = "true|false")]
+    bom:Option<bool>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    input:InputArgs,
+    #[command(flatten)]
+    output:OutputArgs,
     #[clap(short, long, default_value = "config.toml")]
     config: PathBuf,
+    /// only read the header, then print the calculations that would run and exit
+    #[clap(long)]
+    dry_run:bool,
+    /// process the input in chunks without loading it into memory. Only element-wise calculations are supported
     #[clap(long)]
-    gen_config:Option<PathBuf>,
+    stream:bool,
+    /// only write the first N rows of the result, for spot checks
+    #[clap(long, value_name = "N")]
+    head:Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatsArgs {
+    #[command(flatten)]
+    input:InputArgs,
+    /// calculation config to take the parsing and number settings from. Its calculations are not run
+    #[clap(short, long)]
+    config:Option<PathBuf>,
 }
 
+#[derive(clap::Args, Debug)]
+struct HeadArgs {
+    #[command(flatten)]
+    input:InputArgs,
+    /// calculation config to take the parsing settings from. Its calculations are not run
+    #[clap(short, long)]
+    config:Option<PathBuf>,
+    /// number of data rows to print
+    #[clap(short = 'n', long, default_value = "10")]
+    rows:usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    #[command(flatten)]
+    input:InputArgs,
+    #[command(flatten)]
+    output:OutputArgs,
+    /// calculation config to take the parsing and output settings from. Its calculations are not run
+    #[clap(short, long)]
+    config:Option<PathBuf>,
+    /// column delimiter of the output. Defaults to the output_delimeter of the config, then the input delimiter
+    #[clap(long, value_name = "DELIM")]
+    to_delimiter:Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct GenConfigArgs {
+    /// where to write the template
+    #[clap(default_value = "config.toml")]
+    path:PathBuf,
+}
+
+/// number of data rows held in memory at a time with --stream
+const STREAM_CHUNK_ROWS:usize = 100_000;
+
 #[derive(Serialize,Deserialize)]
 struct CalcOptions {
-    left:String,
-    right:String,
-    operation:String,
+    left:Option<String>,
+    /// leave out for single operand operations such as "abs" or "ln"
+    right:Option<String>,
+    operation:Option<String>,
+    /// an arithmetic expression like "(col_a + col_b) / col_c" used instead of left, operation and right
+    expression:Option<String>,
     result:String,
+    /// overwrite the result column if it already exists instead of appending a duplicate
+    overwrite:Option<bool>,
+    /// 0-based index to place the result column at, e.g. 0 to make it the first column. Defaults to the end
+    insert_at:Option<usize>,
+    /// how the results are written, e.g. "{:.4}" or "{:e}". Overrides output_precision for this calculation
+    format:Option<String>,
+    /// add a column numbering the rows from this value, 0 or 1, instead of calculating anything
+    row_index:Option<usize>,
+    /// compare left against this number with a comparison operation and write if_true or if_false
+    threshold:Option<f64>,
+    if_true:Option<String>,
+    if_false:Option<String>,
 }
 
 #[derive(Serialize,Deserialize)]
 struct CalcConfig {
     line_offset:Option<usize>,
+    /// regex matching leading preamble lines, e.g. "^[#%]". Applied after line_offset
+    preamble_pattern:Option<String>,
+    /// delimiter between columns. Prefix with "regex:" to split on a regex, e.g. "regex:\\s+" for any run
+    /// of tabs and spaces
     column_delimeter:Option<String>,
+    /// delimiter used when writing the output. Defaults to column_delimeter, or tab for a regex delimiter
+    output_delimeter:Option<String>,
+    quoting:Option<bool>,
+    whitespace_delimited:Option<bool>,
+    /// data lines starting with this prefix are skipped
+    comment_prefix:Option<String>,
+    /// set to false if the input has no header row. Columns are then named col_1, col_2, ...
+    has_header:Option<bool>,
+    /// trim whitespace around headers and cells, e.g. for "a , b , c"
+    trim:Option<bool>,
+    /// whether to write the header row to the output. Defaults to has_header
+    write_header:Option<bool>,
+    /// wrap every output cell in double quotes
+    quote_all:Option<bool>,
+    /// write the output as "tsv", "csv", "json" or "markdown" instead of with the output delimiter
+    output_format:Option<String>,
+    output_precision:Option<usize>,
+    /// write results without trailing zeros, e.g. 3 instead of 3.00. Applies on top of output_precision
+    trim_trailing_zeros:Option<bool>,
+    /// written wherever a result is NaN. Defaults to "NaN"
+    nan_token:Option<String>,
+    /// value used for missing cells before any calculation runs
+    na_fill:Option<f64>,
+    /// cell values treated as missing. Defaults to "NA", "null", "" and "-"
+    na_tokens:Option<Vec<String>>,
+    /// digit grouping character ignored when parsing numbers, e.g. "," for 1,234.5
+    thousands_sep:Option<char>,
+    /// decimal mark used by the input, e.g. "," for 1234,5
+    decimal_sep:Option<char>,
+    /// match column patterns regardless of case. More headers may match, so patterns can become ambiguous
+    case_insensitive:Option<bool>,
+    /// fail on the first cell used by a calculation that isn't a number instead of treating it as NaN
+    strict:Option<bool>,
+    #[serde(default)]
     calculation:Vec<CalcOptions>,
 }
 
@@ -42,67 +213,817 @@ impl Default for CalcConfig {
     fn default() -> Self {
 
         let op1 = CalcOptions {
-            left:"column name pattern 1".to_string(),
-            right:"column name pattern 2".to_string(),
-            operation: "+".to_string(),
-            result:"new column name".to_string()
+            left:Some("column name pattern 1".to_string()),
+            right:Some("column name pattern 2".to_string()),
+            operation: Some("+".to_string()),
+            expression: None,
+            result:"new column name".to_string(),
+            overwrite: None,
+            insert_at: None,
+            format: None,
+            row_index: None,
+            threshold: None,
+            if_true: None,
+            if_false: None,
         };
 
         let op2 = CalcOptions {
-            left:"column name pattern 1".to_string(),
-            right:"new column name".to_string(),
-            operation: "/".to_string(),
-            result:"new column name 2".to_string()
+            left:Some("column name pattern 1".to_string()),
+            right:Some("new column name".to_string()),
+            operation: Some("/".to_string()),
+            expression: None,
+            result:"new column name 2".to_string(),
+            overwrite: None,
+            insert_at: None,
+            format: None,
+            row_index: None,
+            threshold: None,
+            if_true: None,
+            if_false: None,
         };
 
-        Self { calculation: vec![op1,op2], line_offset: Some(0), column_delimeter: Some("\t".to_string()) }
+        Self {
+            calculation: vec![op1,op2],
+            line_offset: Some(0),
+            preamble_pattern: None,
+            column_delimeter: Some("\t".to_string()),
+            output_delimeter: None,
+            quoting: None,
+            whitespace_delimited: None,
+            comment_prefix: None,
+            has_header: None,
+            trim: None,
+            write_header: None,
+            quote_all: None,
+            output_format: None,
+            output_precision: None,
+            trim_trailing_zeros: None,
+            nan_token: None,
+            na_fill: None,
+            na_tokens: None,
+            thousands_sep: None,
+            decimal_sep: None,
+            case_insensitive: None,
+            strict: None,
+        }
     }
 }
 
-fn main() -> Result<(),Box<dyn Error>> {
-    
-    let args = Args::parse();
+/// formats the output can be written in, see `CalcConfig::output_format`
+#[derive(Clone,Copy,PartialEq)]
+enum OutputFormat {
+    Tsv,
+    Csv,
+    Json,
+    Markdown,
+}
 
-    if let Some(config_file) = args.gen_config {
-        println!("writing config to {:?}",config_file);
-        let mut f = File::create(&config_file).expect("cannot create config file");
-        f.write_all(toml::to_string(&CalcConfig::default()).unwrap().as_bytes()).expect("cannot write to file");
-        return Ok(())
-    };
+const OUTPUT_FORMATS:[&str;4] = ["tsv","csv","json","markdown"];
+
+impl OutputFormat {
+    fn parse(format:&str) -> Result<Self,String> {
+        match format {
+            "tsv" => Ok(OutputFormat::Tsv),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" if cfg!(feature = "json") => Ok(OutputFormat::Json),
+            "json" => Err("output_format 'json' is not available: built without the json feature".to_string()),
+            "markdown" => Ok(OutputFormat::Markdown),
+            _ => Err(format!("unknown output_format '{}'. Supported formats are: {}",format,OUTPUT_FORMATS.join(" ")))
+        }
+    }
+
+    /// whether the output is delimited text, which can be written chunk by chunk with --stream
+    fn is_delimited(self) -> bool {
+        matches!(self,OutputFormat::Tsv | OutputFormat::Csv)
+    }
+}
+
+impl CalcConfig {
+    /// the parsed output_format, if set
+    fn output_format(&self) -> Result<Option<OutputFormat>,String> {
+        self.output_format.as_deref().map(OutputFormat::parse).transpose()
+    }
+
+    /// checks that every calculation refers to columns that resolve to exactly one header, accounting for
+    /// the columns produced by earlier calculations. All problems are collected rather than stopping at the first.
+    fn validate(&self,headers:&[String]) -> Result<(),Vec<String>> {
+        let mut headers = headers.to_vec();
+        let mut errors = vec![];
+        for (i,calc) in self.calculation.iter().enumerate() {
+            let patterns = if let Some(start) = calc.row_index {
+                if start > 1 {
+                    errors.push(format!("calculation {} ('{}'): row_index must be 0 or 1, found {}",i + 1,calc.result,start));
+                }
+                vec![]
+            }else if let Some(threshold) = calc.threshold {
+                match (&calc.left,&calc.operation) {
+                    (Some(left),Some(operation)) if COMPARISONS.contains(&operation.as_str()) => vec![left.to_string()],
+                    _ => {
+                        errors.push(format!("calculation {} ('{}'): threshold {} needs left and a comparison operation ({})",i + 1,calc.result,threshold,COMPARISONS.join(" ")));
+                        vec![]
+                    }
+                }
+            }else if let Some(expression) = &calc.expression {
+                match expression_columns(expression) {
+                    Ok(patterns) => patterns,
+                    Err(e) => {
+                        errors.push(format!("calculation {} ('{}'): {}",i + 1,calc.result,e));
+                        vec![]
+                    }
+                }
+            }else {
+                match (&calc.left,&calc.operation,&calc.right) {
+                    (Some(left),Some(operation),None) if UNARY_OPERATIONS.contains(&operation.as_str()) => {
+                        vec![left.to_string()]
+                    }
+                    // check the name first so a misspelled unary operation isn't reported as a missing operand
+                    (Some(left),Some(operation),None) if !OPERATIONS.contains(&operation.as_str()) && !COMPARISONS.contains(&operation.as_str()) => {
+                        errors.push(format!("calculation {} ('{}'): {}",i + 1,calc.result,SheetError::UnknownOperation(operation.to_string())));
+                        vec![left.to_string()]
+                    }
+                    (Some(left),Some(operation),Some(right)) => {
+                        if !OPERATIONS.contains(&operation.as_str()) && !COMPARISONS.contains(&operation.as_str()) {
+                            errors.push(format!("calculation {} ('{}'): unknown operation {}. Supported operations are: {} {}",i + 1,calc.result,operation,OPERATIONS.join(" "),COMPARISONS.join(" ")));
+                        }
+                        vec![left.to_string(),right.to_string()]
+                    }
+                    _ => {
+                        errors.push(format!("calculation {} ('{}'): needs either an expression, left and a unary operation, or all of left, operation and right",i + 1,calc.result));
+                        vec![]
+                    }
+                }
+            };
+            for pattern in patterns {
+                if let Err(e) = column_index_with_case(&headers, &pattern, self.case_insensitive.unwrap_or(false)) {
+                    errors.push(format!("calculation {} ('{}'): {}",i + 1,calc.result,e));
+                }
+            }
+            if !(calc.overwrite.unwrap_or(false) && headers.contains(&calc.result)) {
+                headers.push(calc.result.clone());
+            }
+            if let Some(at) = calc.insert_at {
+                if at >= headers.len() {
+                    errors.push(format!("calculation {} ('{}'): insert_at {} is past the last of {} column(s)",i + 1,calc.result,at,headers.len()));
+                }else {
+                    place_result(&mut headers, &calc.result, at);
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        }else {
+            Err(errors)
+        }
+    }
+}
+
+fn main() {
+    // errors are reported with their Display message rather than the Debug output of returning them from main
+    if let Err(e) = run() {
+        eprintln!("Error: {}",e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(),Box<dyn Error>> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Run(args)) => run_config(args),
+        Some(Command::Stats(args)) => stats(args),
+        Some(Command::Head(args)) => head(args),
+        Some(Command::Convert(args)) => convert(args),
+        Some(Command::GenConfig(args)) => gen_config(&args.path),
+        None => run_config(cli.run),
+    }
+}
+
+fn gen_config(config_file:&Path) -> Result<(),Box<dyn Error>> {
+    println!("writing config to {:?}",config_file);
+    let mut f = File::create(config_file).map_err(|e| format!("cannot create config file {:?}: {}",config_file,e))?;
+    f.write_all(toml::to_string(&CalcConfig::default())?.as_bytes())?;
+    Ok(())
+}
+
+fn run_config(args:RunArgs) -> Result<(),Box<dyn Error>> {
 
     if !args.config.exists() {
-        println!("calculation config not found. You can generate a template by passing --gen-config=config.toml");
+        println!("calculation config not found. You can generate a template with `sheet-calc gen-config config.toml`");
         Err(format!("calculation config not found: {:?}",args.config))?
     }
+    let config = read_config(&args.config)?;
+    let output_format = config.output_format()?;
+
+    let (input,parse_opts) = open_first_input(&args.input, &config)?;
+
+    if args.dry_run {
+        let headers = match input {
+            Some(input) => read_header(input, &parse_opts)?.column_headers,
+            None => read_workbook(&args.input.input[0], &args.input, &parse_opts)?.column_headers().to_vec(),
+        };
+        return dry_run(headers, &config)
+    }
 
-    let mut conf_file = File::open(&args.config)?;
+    if args.stream {
+        if args.input.input.len() > 1 {
+            Err("--stream only supports a single input")?
+        }
+        if args.head.is_some() {
+            Err("--head is not supported with --stream")?
+        }
+        let Some(input) = input else {
+            Err("--stream is not supported for .xlsx input")?
+        };
+        if output_format.is_some_and(|format| !format.is_delimited()) {
+            Err(format!("output_format '{}' is not supported with --stream",config.output_format.as_deref().unwrap_or_default()))?
+        }
+        if config.calculation.iter().any(|calc| calc.row_index.is_some()) {
+            // every chunk would count from the start again
+            Err("row_index calculations are not supported with --stream")?
+        }
+        eprintln!("streaming calculations in chunks of {} rows ...",STREAM_CHUNK_ROWS);
+        let mut validated = false;
+        let mut n_chunk_rows = 0;
+        let n_rows = write_output_with(&args.output.output, |output|{
+            process_streaming(input, output, &parse_opts, STREAM_CHUNK_ROWS, |chunk|{
+                configure_spreadsheet(chunk, &config);
+                configure_format(chunk, output_format);
+                configure_output(chunk, &args.output);
+                if !validated {
+                    check_config(&config, chunk.column_headers())?;
+                    validated = true;
+                }
+                // rows of cells rejected by strict parsing are counted from the start of the chunk
+                let row_offset = n_chunk_rows;
+                n_chunk_rows += chunk.n_rows();
+                run_calculations(chunk, &config, false).map_err(|e| match e {
+                    SheetError::InvalidNumber { row, column, value } => SheetError::InvalidNumber { row: row + row_offset, column, value },
+                    e => e,
+                })
+            }).map_err(|e| e.into())
+        })?;
+        eprintln!("wrote {} rows to {}",n_rows,args.output.output.to_string_lossy());
+        return Ok(())
+    }
+
+    let mut spreadsheet = read_inputs(input, &args.input, &parse_opts)?;
+
+    configure_spreadsheet(&mut spreadsheet, &config);
+    configure_format(&mut spreadsheet, output_format);
+    configure_output(&mut spreadsheet, &args.output);
+
+    check_config(&config, spreadsheet.column_headers())?;
+
+    eprintln!("running calculations ...");
+    run_calculations(&mut spreadsheet, &config, args.input.progress())?;
+
+    if let Some(n) = args.head {
+        spreadsheet = spreadsheet.head(n);
+    }
+
+    eprintln!("writing new spreadsheet to {}",args.output.output.to_string_lossy());
+    write_output(&args.output.output, &render(&spreadsheet, output_format)?)?;
+
+    Ok(())
+}
+
+/// prints a table of statistics with one row per input column. Cells that fail to parse are counted as missing.
+fn stats(args:StatsArgs) -> Result<(),Box<dyn Error>> {
+    let config = read_optional_config(args.config.as_deref())?;
+    let (input,parse_opts) = open_first_input(&args.input, &config)?;
+    let mut spreadsheet = read_inputs(input, &args.input, &parse_opts)?;
+    configure_spreadsheet(&mut spreadsheet, &config);
+
+    let format = spreadsheet.number_format();
+    let stats = [Stat::Mean,Stat::Std,Stat::Min,Stat::Median,Stat::Max];
+    let mut table = SpreadSheet2DBuilder::new().column_headers(
+        ["column","count","missing","mean","std","min","median","max"].iter().map(|h| h.to_string()).collect()
+    );
+    for (header,column) in spreadsheet.column_headers().iter().zip(spreadsheet.columns_numeric()) {
+        let values:Vec<f64> = column.iter().cloned().filter(|x| !x.is_nan()).collect();
+        let mut row = vec![header.clone(),values.len().to_string(),(column.len() - values.len()).to_string()];
+        row.extend(stats.iter().map(|stat| format.format(stat.compute(&values))));
+        table = table.push_row(row);
+    }
+    write_output(Path::new("-"), &table.build()?.to_string())
+}
+
+/// prints the preamble, header and first rows of the input
+fn head(args:HeadArgs) -> Result<(),Box<dyn Error>> {
+    let config = read_optional_config(args.config.as_deref())?;
+    let (input,parse_opts) = open_first_input(&args.input, &config)?;
+    let mut spreadsheet = read_inputs(input, &args.input, &parse_opts)?;
+    configure_spreadsheet(&mut spreadsheet, &config);
+    write_output(Path::new("-"), &spreadsheet.head(args.rows).to_string())
+}
+
+/// rewrites the input with the output settings of the config and the command line, without running calculations
+fn convert(args:ConvertArgs) -> Result<(),Box<dyn Error>> {
+    let config = read_optional_config(args.config.as_deref())?;
+    let output_format = config.output_format()?;
+    let (input,parse_opts) = open_first_input(&args.input, &config)?;
+    let mut spreadsheet = read_inputs(input, &args.input, &parse_opts)?;
+    configure_spreadsheet(&mut spreadsheet, &config);
+    configure_format(&mut spreadsheet, output_format);
+    configure_output(&mut spreadsheet, &args.output);
+    if let Some(delim) = &args.to_delimiter {
+        spreadsheet.set_output_delimiter(delim);
+    }
+    eprintln!("writing converted spreadsheet to {}",args.output.output.to_string_lossy());
+    write_output(&args.output.output, &render(&spreadsheet, output_format)?)
+}
+
+fn read_config(path:&Path) -> Result<CalcConfig,Box<dyn Error>> {
+    let mut conf_file = File::open(path)?;
     let mut conf_string = String::new();
     conf_file.read_to_string(&mut conf_string)?;
-    let config:CalcConfig = toml::from_str(&conf_string)?;
-    
-    let mut f = File::open(&args.input)?;
+    Ok(toml::from_str(&conf_string)?)
+}
 
-    let mut s = String::new();
-    println!("reading file ...");
-    f.read_to_string(&mut s)?;
+/// reads the config if one was given. Without one every setting is left at its default and there are no calculations
+fn read_optional_config(path:Option<&Path>) -> Result<CalcConfig,Box<dyn Error>> {
+    match path {
+        Some(path) => read_config(path),
+        None => Ok(toml::from_str("")?),
+    }
+}
+
+/// an opened text input, or `None` for a workbook, which is read from its path instead
+type OpenedInput = Option<Box<dyn BufRead>>;
+
+/// opens the first input and works out how to parse it from the config, detecting the delimiter if asked to.
+fn open_first_input(args:&InputArgs,config:&CalcConfig) -> Result<(OpenedInput,ParseOptions),Box<dyn Error>> {
+    let mut input = if is_xlsx(&args.input[0]) { None } else { Some(open_input(&args.input[0],args.progress())?) };
+
+    let mut col_delimeter = config.column_delimeter.clone();
+    if let Some(input) = input.as_mut().filter(|_| args.auto_delimiter && col_delimeter.is_none() && !config.whitespace_delimited.unwrap_or(false)) {
+        col_delimeter = detect_input_delimiter(input, config)?;
+        match &col_delimeter {
+            Some(delim) => eprintln!("detected column delimiter {:?}",delim),
+            None => eprintln!("could not detect the column delimiter, falling back to tab"),
+        }
+    }
+
+    let parse_opts = ParseOptions {
+        col_delimeter: col_delimeter.unwrap_or(String::from("\t")),
+        line_offset: config.line_offset.unwrap_or(0),
+        preamble_pattern: config.preamble_pattern.clone(),
+        quoting: config.quoting.unwrap_or(false),
+        whitespace_delimited: config.whitespace_delimited.unwrap_or(false),
+        comment_prefix: config.comment_prefix.clone(),
+        has_header: config.has_header.unwrap_or(true),
+        trim: config.trim.unwrap_or(false),
+    };
+    Ok((input,parse_opts))
+}
+
+/// parses the opened first input and stacks the remaining inputs below it
+fn read_inputs(input:OpenedInput,args:&InputArgs,parse_opts:&ParseOptions) -> Result<SpreadSheet2D,Box<dyn Error>> {
+    let mut spreadsheet = match input {
+        Some(input) => {
+            eprintln!("reading file ...");
+            let s = read_input(input)?;
+            eprintln!("parsing spreadsheet ...");
+            SpreadSheet2D::from_string_with_options(s,parse_opts)?
+        }
+        None => {
+            eprintln!("reading workbook ...");
+            read_workbook(&args.input[0], args, parse_opts)?
+        }
+    };
+
+    for path in &args.input[1..] {
+        eprintln!("stacking {} ...",path.to_string_lossy());
+        let other = if is_xlsx(path) {
+            read_workbook(path, args, parse_opts)
+        }else {
+            read_input(open_input(path,args.progress())?)
+                .and_then(|s| Ok(SpreadSheet2D::from_string_with_options(s,parse_opts)?))
+        }.map_err(|e| format!("cannot parse {:?}: {}",path,e))?;
+        spreadsheet.vstack(&other).map_err(|e| format!("input {:?} doesn't match the columns of {:?}: {}",path,args.input[0],e))?;
+    }
+    Ok(spreadsheet)
+}
+
+/// reads the worksheet selected by --sheet from an .xlsx input with the preamble, header, comment and trim
+/// settings of the config
+fn read_workbook(path:&Path,args:&InputArgs,parse_opts:&ParseOptions) -> Result<SpreadSheet2D,Box<dyn Error>> {
+    #[cfg(feature = "xlsx")]
+    return Ok(SpreadSheet2D::from_xlsx_with_options(path, args.sheet.as_deref(), parse_opts)?);
+    #[cfg(not(feature = "xlsx"))]
+    {
+        let _ = (args,parse_opts);
+        Err(format!("can't read {}: built without the xlsx feature",path.display()))?
+    }
+}
+
+/// applies the command line output settings, which take precedence over the config
+fn configure_output(spreadsheet:&mut SpreadSheet2D,args:&OutputArgs) {
+    if args.crlf {
+        spreadsheet.set_line_ending(LineEnding::Crlf);
+    }
+    if let Some(bom) = args.bom {
+        spreadsheet.set_write_bom(bom);
+    }
+}
+
+/// sets the delimiter and quoting of a tsv or csv output format, overriding output_delimeter
+fn configure_format(spreadsheet:&mut SpreadSheet2D,format:Option<OutputFormat>) {
+    match format {
+        Some(OutputFormat::Tsv) => spreadsheet.set_output_delimiter("\t"),
+        Some(OutputFormat::Csv) => {
+            spreadsheet.set_output_delimiter(",");
+            spreadsheet.set_quoting(true);
+        }
+        _ => (),
+    }
+}
+
+/// writes the spreadsheet in the output format, or as delimited text if none is set
+fn render(spreadsheet:&SpreadSheet2D,format:Option<OutputFormat>) -> Result<String,Box<dyn Error>> {
+    match format {
+        #[cfg(feature = "json")]
+        Some(OutputFormat::Json) => Ok(spreadsheet.to_json()?),
+        #[cfg(not(feature = "json"))]
+        Some(OutputFormat::Json) => Err("can't write json: built without the json feature")?,
+        Some(OutputFormat::Markdown) => Ok(spreadsheet.to_markdown(true)),
+        _ => Ok(spreadsheet.to_string()),
+    }
+}
+
+/// applies the output and numeric parsing settings of the config to the spreadsheet
+fn configure_spreadsheet(spreadsheet:&mut SpreadSheet2D,config:&CalcConfig) {
+
+    if let Some(write_header) = config.write_header {
+        spreadsheet.set_write_header(write_header);
+    }
 
-    println!("parsing spreadsheet ...");
-    let mut spreadsheet = SpreadSheet2D::from_string(s,&config.column_delimeter.unwrap_or(String::from("\t")),config.line_offset.unwrap_or(0));
+    if let Some(delim) = &config.output_delimeter {
+        spreadsheet.set_output_delimiter(delim);
+    }
+
+    if let Some(quote_all) = config.quote_all {
+        spreadsheet.set_quote_all(quote_all);
+    }
+
+    if config.trim_trailing_zeros.unwrap_or(false) {
+        spreadsheet.set_number_format(NumberFormat::TrimTrailingZeros(config.output_precision));
+    }else if let Some(decimals) = config.output_precision {
+        spreadsheet.set_output_precision(decimals);
+    }
+
+    if let Some(case_insensitive) = config.case_insensitive {
+        spreadsheet.set_case_insensitive(case_insensitive);
+    }
+
+    if let Some(token) = &config.nan_token {
+        spreadsheet.set_nan_token(token);
+    }
 
-    println!("running calculations ...");
+    let mut numeric_options = NumericOptions {
+        na_fill: config.na_fill,
+        thousands_sep: config.thousands_sep,
+        decimal_sep: config.decimal_sep,
+        ..Default::default()
+    };
+    if let Some(na_tokens) = config.na_tokens.clone() {
+        numeric_options.na_tokens = na_tokens;
+    }
+    spreadsheet.set_numeric_options(numeric_options);
+}
 
+/// validates the config against the headers, printing every problem found
+fn check_config(config:&CalcConfig,headers:&[String]) -> Result<(),SheetError> {
     for calc in &config.calculation {
+        if let Some(Err(e)) = calc.format.as_deref().map(NumberFormat::parse) {
+            eprintln!("warning: {} for '{}', using the default format",e,calc.result);
+        }
+    }
+    if let Err(errors) = config.validate(headers) {
+        eprintln!("found {} problem(s) with the calculation config:",errors.len());
+        for e in &errors {
+            eprintln!("  {}",e);
+        }
+        Err("invalid calculation config")?
+    }
+    Ok(())
+}
+
+/// runs the calculations in order, printing each one as it starts if `progress` is set
+fn run_calculations(spreadsheet:&mut SpreadSheet2D,config:&CalcConfig,progress:bool) -> Result<(),SheetError> {
+    let number_format = spreadsheet.number_format();
+    for (i,calc) in config.calculation.iter().enumerate() {
+        if progress {
+            eprintln!("  [{}/{}] {}",i + 1,config.calculation.len(),calc.result);
+        }
+        // formats that fail to parse were reported by check_config and fall back to the default
+        if let Some(format) = &calc.format {
+            spreadsheet.set_number_format(NumberFormat::parse(format).unwrap_or_default());
+        }
+        let result = check_strict(spreadsheet, config, calc)
+            .and_then(|_| run_calculation(spreadsheet, calc))
+            .and_then(|_| move_result(spreadsheet, calc));
+        spreadsheet.set_number_format(number_format);
+        result?
+    }
+    Ok(())
+}
+
+/// with `strict` set in the config, checks that every cell of the columns read by the calculation is a number
+fn check_strict(spreadsheet:&SpreadSheet2D,config:&CalcConfig,calc:&CalcOptions) -> Result<(),SheetError> {
+    if !config.strict.unwrap_or(false) || calc.row_index.is_some() {
+        return Ok(())
+    }
+    let patterns = match &calc.expression {
+        Some(expression) => expression_columns(expression)?,
+        None => calc.left.iter().chain(&calc.right).cloned().collect(),
+    };
+    for pattern in patterns {
+        spreadsheet.column_numeric_strict(&pattern)?;
+    }
+    Ok(())
+}
+
+/// moves the result column of a calculation to its insert_at index
+fn move_result(spreadsheet:&mut SpreadSheet2D,calc:&CalcOptions) -> Result<(),SheetError> {
+    let Some(at) = calc.insert_at else {
+        return Ok(())
+    };
+    // the result is either appended or overwrites an existing column, so it is the last one with its name
+    match spreadsheet.column_headers().iter().rposition(|header| header == &calc.result) {
+        Some(from) => spreadsheet.move_column(from, at),
+        None => Err(format!("result column '{}' not found",calc.result))?
+    }
+}
+
+/// mirrors `move_result` on a list of headers
+fn place_result(headers:&mut Vec<String>,result:&str,at:usize) {
+    if let Some(from) = headers.iter().rposition(|header| header == result) {
+        let header = headers.remove(from);
+        headers.insert(at, header);
+    }
+}
+
+fn run_calculation(spreadsheet:&mut SpreadSheet2D,calc:&CalcOptions) -> Result<(),SheetError> {
+    if let Some(start) = calc.row_index {
+        return spreadsheet.add_index_column(&calc.result, start == 1)
+    }
+    if let Some(threshold) = calc.threshold {
+        let (Some(left),Some(operation)) = (&calc.left,&calc.operation) else {
+            Err(format!("calculation for '{}' with a threshold needs left and operation",calc.result))?
+        };
+        let if_true = calc.if_true.as_deref().unwrap_or("true");
+        let if_false = calc.if_false.as_deref().unwrap_or("false");
+        return spreadsheet.when_compare(left, operation, threshold, if_true, if_false, &calc.result)
+    }
+    if let Some(expression) = &calc.expression {
+        return spreadsheet.column_expr(expression, &calc.result)
+    }
+    let (Some(left),Some(operation)) = (&calc.left,&calc.operation) else {
+        Err(format!("calculation for '{}' needs either an expression or left and operation",calc.result))?
+    };
+    let Some(right) = &calc.right else {
+        return spreadsheet.column_unary_op(left, operation, &calc.result)
+    };
+    if calc.overwrite.unwrap_or(false) {
+        spreadsheet.column_op_overwrite(
+            left,
+            operation,
+            right,
+            &calc.result
+        )
+    }else {
         spreadsheet.column_op(
-            &calc.left,
-            &calc.operation,
-            &calc.right,
+            left,
+            operation,
+            right,
             &calc.result
-        )?
+        )
+    }
+}
+
+/// validates the config against the headers of the input and prints the calculations that would run along
+/// with the columns they resolve to
+fn dry_run(mut headers:Vec<String>,config:&CalcConfig) -> Result<(),Box<dyn Error>> {
+    println!("input columns:");
+    for (i,h) in headers.iter().enumerate() {
+        println!("  col: {} : {}",i + 1,h);
     }
 
-    println!("writing new spreadsheet to {}",args.output.to_string_lossy());
-    let mut new_f = File::create(&args.output)?;
-    new_f.write_all(spreadsheet.to_string().as_bytes())?;
+    check_config(config, &headers)?;
+
+    println!("calculations:");
+    for calc in &config.calculation {
+        let resolve = |pattern:&str| {
+            column_index_with_case(&headers, pattern, config.case_insensitive.unwrap_or(false)).map(|idx| headers[idx].clone())
+        };
+        if let Some(start) = calc.row_index {
+            println!("  '{}' = row number counting from {}",calc.result,start);
+        }else if let (Some(threshold),Some(left),Some(operation)) = (calc.threshold,&calc.left,&calc.operation) {
+            println!("  '{}' = if '{}' {} {} then '{}' else '{}'",calc.result,resolve(left)?,operation,threshold,
+                calc.if_true.as_deref().unwrap_or("true"),calc.if_false.as_deref().unwrap_or("false"));
+        }else if let Some(expression) = &calc.expression {
+            let columns:Result<Vec<String>,_> = expression_columns(expression)?.iter().map(|p| resolve(p)).collect();
+            println!("  '{}' = {} (using {})",calc.result,expression,columns?.join(", "));
+        }else if let (Some(left),Some(operation),Some(right)) = (&calc.left,&calc.operation,&calc.right) {
+            println!("  '{}' = '{}' {} '{}'",calc.result,resolve(left)?,operation,resolve(right)?);
+        }else if let (Some(left),Some(operation)) = (&calc.left,&calc.operation) {
+            println!("  '{}' = {}('{}')",calc.result,operation,resolve(left)?);
+        }
+        if calc.overwrite.unwrap_or(false) && headers.contains(&calc.result) {
+            println!("    overwrites existing column '{}'",calc.result);
+        }else {
+            headers.push(calc.result.clone());
+        }
+        if let Some(at) = calc.insert_at {
+            println!("    placed at column {}",at + 1);
+            place_result(&mut headers, &calc.result, at);
+        }
+    }
 
+    println!("output columns:");
+    for (i,h) in headers.iter().enumerate() {
+        println!("  col: {} : {}",i + 1,h);
+    }
     Ok(())
 }
+
+/// `-` stands for stdin when used as the input and stdout when used as the output
+fn is_std_stream(path:&Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn is_gzip(path:&Path) -> bool {
+    path.extension().map(|ext| ext == "gz").unwrap_or(false)
+}
+
+fn is_xlsx(path:&Path) -> bool {
+    path.extension().map(|ext| ext == "xlsx").unwrap_or(false)
+}
+
+/// opens the input file (or stdin) for reading, decompressing it if it has a .gz extension
+fn open_input(path:&Path,progress:bool) -> Result<Box<dyn BufRead>,Box<dyn Error>> {
+    if is_std_stream(path) {
+        #[cfg(feature = "progress")]
+        if progress {
+            return Ok(Box::new(BufReader::new(ProgressReader::new(io::stdin(), None))))
+        }
+        return Ok(Box::new(io::stdin().lock()))
+    }
+    let f = File::open(path)?;
+    #[cfg(feature = "progress")]
+    let f:Box<dyn Read> = if progress {
+        let total = f.metadata()?.len();
+        Box::new(ProgressReader::new(f, Some(total)))
+    }else {
+        Box::new(f)
+    };
+    #[cfg(not(feature = "progress"))]
+    let _ = progress;
+    if is_gzip(path) {
+        #[cfg(feature = "gzip")]
+        return Ok(Box::new(BufReader::new(GzDecoder::new(f))));
+        #[cfg(not(feature = "gzip"))]
+        Err(format!("can't read {}: built without the gzip feature",path.display()))?
+    }
+    Ok(Box::new(BufReader::new(f)))
+}
+
+/// number of bytes read between progress updates
+#[cfg(feature = "progress")]
+const PROGRESS_INTERVAL:u64 = 1 << 20;
+
+/// prints the number of bytes read so far to stderr, as a percentage if the total size is known. For
+/// compressed input the progress refers to the compressed bytes. This only covers reading: the input is
+/// read to a string in full before it is parsed, and parsing reports nothing.
+#[cfg(feature = "progress")]
+struct ProgressReader<R> {
+    inner:R,
+    total:Option<u64>,
+    n_read:u64,
+    next_report:u64,
+}
+
+#[cfg(feature = "progress")]
+impl<R> ProgressReader<R> {
+    fn new(inner:R,total:Option<u64>) -> Self {
+        Self { inner, total, n_read: 0, next_report: PROGRESS_INTERVAL }
+    }
+
+    fn report(&self) {
+        let mib = self.n_read as f64 / (1 << 20) as f64;
+        match self.total {
+            Some(total) if total > 0 => eprint!("\rread {:.1} MiB ({:.0}%)",mib,100. * self.n_read as f64 / total as f64),
+            _ => eprint!("\rread {:.1} MiB",mib),
+        }
+    }
+}
+
+#[cfg(feature = "progress")]
+impl<R:Read> Read for ProgressReader<R> {
+    fn read(&mut self,buf:&mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.n_read += n as u64;
+        if self.n_read >= self.next_report {
+            self.report();
+            self.next_report = self.n_read + PROGRESS_INTERVAL;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "progress")]
+impl<R> Drop for ProgressReader<R> {
+    // the input is dropped once it has been read, which finishes the progress line
+    fn drop(&mut self) {
+        self.report();
+        eprintln!();
+    }
+}
+
+/// reads the rest of the opened input to a string
+fn read_input(mut input:impl BufRead) -> Result<String,Box<dyn Error>> {
+    let mut s = String::new();
+    input.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+/// guesses the delimiter from the data buffered at the start of the input without consuming it. The
+/// preamble and comment lines are skipped, as is the last line if it was cut off by the end of the buffer.
+fn detect_input_delimiter(input:&mut dyn BufRead,config:&CalcConfig) -> Result<Option<String>,Box<dyn Error>> {
+    let sample = String::from_utf8_lossy(input.fill_buf()?);
+    let mut lines:Vec<&str> = sample.lines().skip(config.line_offset.unwrap_or(0)).collect();
+    if !sample.ends_with('\n') && lines.len() > 1 {
+        lines.pop();
+    }
+    if let Some(pattern) = &config.preamble_pattern {
+        let re = Regex::new(pattern)?;
+        let n_preamble = lines.iter().take_while(|line| re.is_match(line)).count();
+        lines.drain(..n_preamble);
+    }
+    if let Some(prefix) = &config.comment_prefix {
+        lines.retain(|line| !line.starts_with(prefix.as_str()));
+    }
+    Ok(detect_delimiter(&lines.join("\n")))
+}
+
+/// writes the output string to a file (or stdout), compressing it if it has a .gz extension
+fn write_output(path:&Path,s:&str) -> Result<(),Box<dyn Error>> {
+    write_output_with(path, |output| Ok(output.write_all(s.as_bytes())?))
+}
+
+/// opens the output file (or stdout), passes it to `write` and makes sure everything is flushed. The output
+/// is compressed if it has a .gz extension. Files are written to a temporary file next to the target which
+/// is renamed over it once complete, so the output is never left half written.
+fn write_output_with<T>(path:&Path,write:impl FnOnce(&mut dyn Write) -> Result<T,Box<dyn Error>>) -> Result<T,Box<dyn Error>> {
+    if is_std_stream(path) {
+        let mut stdout = io::stdout().lock();
+        let result = write(&mut stdout)?;
+        stdout.flush()?;
+        return Ok(result)
+    }
+    #[cfg(not(feature = "gzip"))]
+    if is_gzip(path) {
+        Err(format!("can't write {}: built without the gzip feature",path.display()))?
+    }
+    let tmp_path = temp_output_path(path)?;
+    let result = write_file(&tmp_path, is_gzip(path), write).and_then(|result|{
+        fs::rename(&tmp_path, path).map_err(|e| format!("cannot move temporary output {:?} to {:?}: {}",tmp_path,path,e))?;
+        Ok(result)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// a hidden file in the same directory as `path`, so the final rename stays on the same file system
+fn temp_output_path(path:&Path) -> Result<PathBuf,Box<dyn Error>> {
+    let Some(file_name) = path.file_name() else {
+        Err(format!("output path {:?} is not a file",path))?
+    };
+    let tmp_name = format!(".{}.{}.tmp",file_name.to_string_lossy(),std::process::id());
+    Ok(path.with_file_name(tmp_name))
+}
+
+fn write_file<T>(path:&Path,gzip:bool,write:impl FnOnce(&mut dyn Write) -> Result<T,Box<dyn Error>>) -> Result<T,Box<dyn Error>> {
+    let f = BufWriter::new(File::create(path)?);
+    #[cfg(feature = "gzip")]
+    let (result,f) = if gzip {
+        let mut encoder = GzEncoder::new(f, Compression::default());
+        let result = write(&mut encoder)?;
+        (result,encoder.finish()?)
+    }else {
+        let mut f = f;
+        let result = write(&mut f)?;
+        (result,f)
+    };
+    // write_output_with rejects .gz outputs before getting here
+    #[cfg(not(feature = "gzip"))]
+    let (result,f) = {
+        let _ = gzip;
+        let mut f = f;
+        (write(&mut f)?,f)
+    };
+    f.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(result)
+}
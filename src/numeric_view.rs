@@ -0,0 +1,100 @@
+
+use ndarray::{Array2, ArrayView1, Axis};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{column_index_with_case, Compute, SheetError, SpreadSheet2D};
+
+/// a cache of every column of a `SpreadSheet2D` parsed to `F`, f64 unless chosen otherwise. Operations run
+/// against the cache without re-parsing, and their results are only written back to the spreadsheet by
+/// `commit`. An f32 view takes half the memory of an f64 one, with the precision trade-off described for
+/// `Precision`.
+pub struct NumericView<F:Compute = f64> {
+    data:Array2<F>,
+    column_headers:Vec<String>,
+    /// number of columns taken from the spreadsheet. Columns after these were computed on the view.
+    n_source_columns:usize,
+    case_insensitive:bool,
+}
+
+impl SpreadSheet2D {
+    /// parses every column once into an f64 `NumericView`. Cells that fail to parse are NaN.
+    pub fn numeric_view(&self) -> NumericView {
+        self.numeric_view_as()
+    }
+
+    /// same as `numeric_view`, parsing to `F`, e.g. `numeric_view_as::<f32>()`
+    pub fn numeric_view_as<F:Compute>(&self) -> NumericView<F> {
+        NumericView {
+            data: self.data.map(|x| F::from(self.numeric_options.parse(x)).unwrap_or(F::nan())),
+            column_headers: self.column_headers.clone(),
+            n_source_columns: self.column_headers.len(),
+            case_insensitive: self.case_insensitive,
+        }
+    }
+}
+
+impl<F:Compute> NumericView<F> {
+
+    pub fn column_headers(&self) -> &[String] {
+        &self.column_headers
+    }
+
+    /// returns the cached values of the matched column
+    pub fn column(&self,col:&str) -> Result<ArrayView1<'_,F>,SheetError> {
+        let idx = column_index_with_case(&self.column_headers, col, self.case_insensitive)?;
+        Ok(self.data.column(idx))
+    }
+
+    /// same as `SpreadSheet2D::column_op`, operating on the cached values
+    pub fn column_op(&mut self,col1:&str,operation:&str,col2:&str,new_col_name:&str) -> Result<(),SheetError> {
+        let col1 = self.column(col1)?.to_vec();
+        let col2 = self.column(col2)?.to_vec();
+        let new_col = SpreadSheet2D::do_operation(&col1,&col2,operation)?;
+        self.push_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// same as `SpreadSheet2D::column_scalar_op`, operating on the cached values
+    pub fn column_scalar_op(&mut self,col:&str,operation:&str,scalar:f64,scalar_on_left:bool,new_col_name:&str) -> Result<(),SheetError> {
+        let column = self.column(col)?.to_vec();
+        let scalars = vec![F::from(scalar).unwrap_or(F::nan());column.len()];
+        let new_col = if scalar_on_left {
+            SpreadSheet2D::do_operation(&scalars,&column,operation)?
+        }else {
+            SpreadSheet2D::do_operation(&column,&scalars,operation)?
+        };
+        self.push_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    /// same as `SpreadSheet2D::map_column`, operating on the cached values
+    pub fn map_column<M>(&mut self,col:&str,new_col_name:&str,f:M) -> Result<(),SheetError>
+    where M: Fn(F) -> F + Sync + Send {
+        let column = self.column(col)?.to_vec();
+        let new_col:Vec<F> = column.par_iter().map(|&x| f(x)).collect();
+        self.push_column(new_col, new_col_name);
+        Ok(())
+    }
+
+    fn push_column(&mut self,values:Vec<F>,new_col_name:&str) {
+        let n_rows = self.data.shape()[0];
+        let to_append = Array2::from_shape_vec((n_rows, 1), values).unwrap();
+        self.data.append(Axis(1), to_append.view()).unwrap();
+        self.column_headers.push(new_col_name.to_string());
+    }
+
+    /// appends the columns computed on this view to `sheet`, formatted with the spreadsheet's output settings.
+    /// The original columns are left untouched. Fails if `sheet` no longer has the same number of rows as the view.
+    pub fn commit(self,sheet:&mut SpreadSheet2D) -> Result<(),SheetError> {
+        let n_rows = self.data.shape()[0];
+        let sheet_rows = sheet.data.shape()[0];
+        if n_rows != sheet_rows {
+            Err(format!("cannot commit numeric view with {} row(s) to spreadsheet with {} row(s)",n_rows,sheet_rows))?
+        }
+        for idx in self.n_source_columns..self.column_headers.len() {
+            let values = sheet.format_values(&self.data.column(idx).to_vec());
+            sheet.append_column(values, &self.column_headers[idx]);
+        }
+        Ok(())
+    }
+}
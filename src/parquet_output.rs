@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+
+use crate::{ColumnType, Number, SheetError, SpreadSheet2D};
+
+impl SpreadSheet2D {
+    /// writes the data rows to a Parquet file with one field per column, typed by `infer_schema`: Int64,
+    /// Float64, Boolean or Utf8. Empty cells are written as nulls, see `ColumnType`. The preamble is not
+    /// written.
+    pub fn to_parquet(&self,path:&Path) -> Result<(),SheetError> {
+        let mut fields = vec![];
+        let mut columns:Vec<ArrayRef> = vec![];
+        for ((header,column_type),column) in self.infer_schema().into_iter().zip(self.data.columns()) {
+            let cells = column.iter().map(|cell| Some(cell.as_str()).filter(|cell| !cell.is_empty()));
+            let (data_type,array):(DataType,ArrayRef) = match column_type {
+                ColumnType::Integer => (DataType::Int64, Arc::new(cells.map(|cell| match Number::parse(cell?, &self.numeric_options) {
+                    Some(Number::Integer(x)) => Some(x),
+                    _ => None,
+                }).collect::<Int64Array>())),
+                ColumnType::Float => (DataType::Float64, Arc::new(cells.map(|cell| match Number::parse(cell?, &self.numeric_options)? {
+                    Number::Integer(x) => Some(x as f64),
+                    Number::Float(x) => Some(x),
+                }).collect::<Float64Array>())),
+                ColumnType::Boolean => (DataType::Boolean, Arc::new(cells.map(|cell| {
+                    cell.map(|cell| cell.eq_ignore_ascii_case("true"))
+                }).collect::<BooleanArray>())),
+                ColumnType::String => (DataType::Utf8, Arc::new(cells.collect::<StringArray>())),
+            };
+            fields.push(Field::new(&header, data_type, true));
+            columns.push(array);
+        }
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
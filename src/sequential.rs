@@ -0,0 +1,169 @@
+
+use crate::{SheetError, SpreadSheet2D, Stat};
+
+/// running operations for `SpreadSheet2D::cumulative`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum CumOp {
+    Sum,
+    Product,
+    Max,
+    Min,
+}
+
+impl CumOp {
+    fn apply(&self,acc:f64,x:f64) -> f64 {
+        match self {
+            CumOp::Sum => acc + x,
+            CumOp::Product => acc * x,
+            CumOp::Max => acc.max(x),
+            CumOp::Min => acc.min(x),
+        }
+    }
+}
+
+/// how running operations treat cells that fail to parse
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum NanPolicy {
+    /// skip the cell and repeat the running value so far
+    #[default]
+    Carry,
+    /// write NaN for the cell and start over from the next value
+    Reset,
+}
+
+/// gap filling methods for `SpreadSheet2D::interpolate_column`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum InterpMethod {
+    /// repeat the last valid value. Gaps before the first valid value stay as they are.
+    Forward,
+    /// repeat the next valid value. Gaps after the last valid value stay as they are.
+    Backward,
+    /// interpolate linearly by row between the valid values on either side. Gaps at the start or end of the
+    /// column stay as they are.
+    Linear,
+}
+
+impl SpreadSheet2D {
+    /// appends the running `op` of the matched column in row order, carrying the running value past cells
+    /// that fail to parse. Rows before the first number are NaN.
+    pub fn cumulative(&mut self,col:&str,new_col_name:&str,op:CumOp) -> Result<(),SheetError> {
+        self.cumulative_with_policy(col, new_col_name, op, NanPolicy::Carry)
+    }
+
+    /// same as `cumulative`, with the handling of cells that fail to parse set by `nan_policy`
+    pub fn cumulative_with_policy(&mut self,col:&str,new_col_name:&str,op:CumOp,nan_policy:NanPolicy) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let mut acc:Option<f64> = None;
+        let new_col:Vec<f64> = column.iter().map(|&x|{
+            if x.is_nan() {
+                return match nan_policy {
+                    NanPolicy::Carry => acc.unwrap_or(f64::NAN),
+                    NanPolicy::Reset => {
+                        acc = None;
+                        f64::NAN
+                    }
+                }
+            }
+            let value = acc.map(|acc| op.apply(acc, x)).unwrap_or(x);
+            acc = Some(value);
+            value
+        }).collect();
+        self.append_numeric_column(&new_col, new_col_name);
+        Ok(())
+    }
+
+    /// appends `x[i] - x[i-lag]` of the matched column, e.g. a lag of 1 for the change between consecutive
+    /// rows. The first `lag` rows have no predecessor and are NaN. `lag` must be at least 1 and less than
+    /// the number of rows.
+    pub fn row_diff(&mut self,col:&str,new_col_name:&str,lag:usize) -> Result<(),SheetError> {
+        self.row_diff_with_fill(col, new_col_name, lag, f64::NAN)
+    }
+
+    /// same as `row_diff`, writing `fill` to the first `lag` rows
+    pub fn row_diff_with_fill(&mut self,col:&str,new_col_name:&str,lag:usize,fill:f64) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let n_rows = self.n_rows();
+        if lag == 0 || lag >= n_rows {
+            Err(format!("lag must be between 1 and the number of rows minus one ({}), got {}",n_rows.saturating_sub(1),lag))?
+        }
+        let column = self.parse_column(idx);
+        let new_col:Vec<f64> = (0..n_rows).map(|i|{
+            if i < lag {
+                fill
+            }else {
+                column[i] - column[i - lag]
+            }
+        }).collect();
+        self.append_numeric_column(&new_col, new_col_name);
+        Ok(())
+    }
+
+    /// appends `stat` of the matched column over a trailing window of `window` rows ending at each row, e.g.
+    /// `Stat::Mean` for a moving average. Cells that fail to parse are left out of the windows they fall in.
+    /// Rows whose window holds fewer than `window` numbers, including the first `window - 1` rows, are NaN.
+    pub fn rolling(&mut self,col:&str,window:usize,stat:Stat,new_col_name:&str) -> Result<(),SheetError> {
+        self.rolling_with_min_periods(col, window, stat, window, new_col_name)
+    }
+
+    /// same as `rolling`, computing the statistic for every row whose window holds at least `min_periods`
+    /// numbers, so a `min_periods` of 1 averages the partial windows at the start of the column.
+    /// `min_periods` must be between 1 and `window`.
+    pub fn rolling_with_min_periods(&mut self,col:&str,window:usize,stat:Stat,min_periods:usize,new_col_name:&str) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        if window == 0 {
+            Err("window must be at least 1")?
+        }
+        if min_periods == 0 || min_periods > window {
+            Err(format!("min_periods must be between 1 and the window of {}, got {}",window,min_periods))?
+        }
+        let column = self.parse_column(idx);
+        let new_col:Vec<f64> = (0..column.len()).map(|i|{
+            let start = (i + 1).saturating_sub(window);
+            let values:Vec<f64> = column[start..=i].iter().cloned().filter(|x| !x.is_nan()).collect();
+            if values.len() < min_periods {
+                f64::NAN
+            }else {
+                stat.compute(&values)
+            }
+        }).collect();
+        self.append_numeric_column(&new_col, new_col_name);
+        Ok(())
+    }
+
+    /// fills cells of the matched column that fail to parse, in place, from the valid values around them.
+    /// Cells that can't be filled with the chosen method keep their original contents, as do all valid cells.
+    pub fn interpolate_column(&mut self,col:&str,method:InterpMethod) -> Result<(),SheetError> {
+        let idx = self.resolve_column(col)?;
+        let column = self.parse_column(idx);
+        let valid:Vec<usize> = (0..column.len()).filter(|&i| !column[i].is_nan()).collect();
+
+        let mut filled = vec![];
+        for window in valid.windows(2) {
+            let (start,end) = (window[0],window[1]);
+            for i in start + 1..end {
+                let value = match method {
+                    InterpMethod::Forward => column[start],
+                    InterpMethod::Backward => column[end],
+                    InterpMethod::Linear => {
+                        let t = (i - start) as f64 / (end - start) as f64;
+                        column[start] + (column[end] - column[start]) * t
+                    }
+                };
+                filled.push((i,value));
+            }
+        }
+        if let (Some(&first),Some(&last)) = (valid.first(),valid.last()) {
+            match method {
+                InterpMethod::Forward => filled.extend((last + 1..column.len()).map(|i| (i,column[last]))),
+                InterpMethod::Backward => filled.extend((0..first).map(|i| (i,column[first]))),
+                InterpMethod::Linear => (),
+            }
+        }
+
+        for (row,value) in filled {
+            self.data[[row,idx]] = self.format_value(value);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{BufRead, Write};
+
+use crate::{skip_bom, synthesize_headers, LineSplitter, ParseOptions, PreambleMatcher, SheetError, SpreadSheet2D};
+
+/// reads delimited text from `reader` in chunks of `chunk_rows` data rows, runs `apply` on each chunk and
+/// writes the result to `writer` as it goes, so the whole file never has to be held in memory. Returns the
+/// number of data rows processed.
+///
+/// Each chunk is a regular `SpreadSheet2D` holding the preamble, the headers and a slice of the rows, and
+/// `apply` is called once per chunk in order. It is always called at least once, with an empty chunk if
+/// the input has no data rows, so the header of the output reflects the columns `apply` adds. Only
+/// operations that work row by row (`column_op`, `column_expr`, `column_scalar_op`, `map_column`, ...)
+/// give the same result as on the full spreadsheet. Anything that needs whole-column context, such as
+/// sorting, aggregates, normalization, cumulative or rolling operations, would only see the current
+/// chunk and must not be used here.
+pub fn process_streaming<R,W,F>(mut reader:R,mut writer:W,opts:&ParseOptions,chunk_rows:usize,mut apply:F) -> Result<usize,SheetError>
+where R: BufRead, W: Write, F: FnMut(&mut SpreadSheet2D) -> Result<(),SheetError> {
+
+    if chunk_rows == 0 {
+        Err("chunk_rows must be at least 1")?
+    }
+
+    let matcher = PreambleMatcher::new(opts)?;
+    let splitter = LineSplitter::new(opts)?;
+    let bom = skip_bom(&mut reader)?;
+    let mut lines = reader.lines();
+
+    let mut preamble = vec![];
+    let mut next_line = None;
+    for line in lines.by_ref() {
+        let line = line?;
+        if matcher.matches(preamble.len(), &line) {
+            preamble.push(line);
+        }else {
+            next_line = Some(line);
+            break
+        }
+    }
+
+    let mut line_number = preamble.len();
+    let is_comment = |row:&str| opts.comment_prefix.as_ref().is_some_and(|prefix| row.starts_with(prefix.as_str()));
+
+    // data rows read ahead of the current chunk, used to infer the column count of header-less input
+    let mut pending:Vec<(usize,String)> = vec![];
+
+    let column_headers = if opts.has_header {
+        line_number += 1;
+        match next_line {
+            Some(header) => splitter.split(&header).map_err(|message| SheetError::MalformedLine { line: line_number, message })?,
+            None => Err(format!("unexpected end of input: expected a header row after {} preamble line(s)",preamble.len()))?
+        }
+    }else {
+        loop {
+            line_number += 1;
+            let line = match next_line.take() {
+                Some(line) => line,
+                None => match lines.next() {
+                    Some(line) => line?,
+                    None => Err(format!("unexpected end of input: expected a data row after {} preamble line(s)",preamble.len()))?
+                }
+            };
+            if is_comment(&line) {
+                continue
+            }
+            let n = splitter.split(&line).map_err(|message| SheetError::MalformedLine { line: line_number, message })?.len();
+            pending.push((line_number,line));
+            break synthesize_headers(n)
+        }
+    };
+
+    let mut n_processed = 0;
+    let mut first_chunk = true;
+    let mut exhausted = false;
+
+    while first_chunk || !exhausted {
+        let mut rows = std::mem::take(&mut pending);
+        while rows.len() < chunk_rows {
+            match lines.next() {
+                Some(line) => {
+                    line_number += 1;
+                    let line = line?;
+                    if !is_comment(&line) {
+                        rows.push((line_number,line));
+                    }
+                }
+                None => {
+                    exhausted = true;
+                    break
+                }
+            }
+        }
+
+        if rows.is_empty() && !first_chunk {
+            break
+        }
+
+        let row_refs:Vec<(usize,&str)> = rows.iter().map(|(n,row)| (*n,row.as_str())).collect();
+        let chunk_preamble = if first_chunk { preamble.clone() } else { vec![] };
+        let mut chunk = SpreadSheet2D::from_rows(chunk_preamble, column_headers.clone(), &row_refs, opts)?;
+        chunk.set_write_bom(bom);
+
+        apply(&mut chunk)?;
+
+        let delim = chunk.output_delimiter().to_string();
+        if first_chunk {
+            writer.write_all(chunk.head_to_string(&delim).as_bytes())?;
+        }
+        writer.write_all(chunk.rows_to_string(&delim).as_bytes())?;
+
+        n_processed += rows.len();
+        first_chunk = false;
+    }
+
+    writer.flush()?;
+    Ok(n_processed)
+}
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use calamine::{open_workbook, Data, ExcelDateTime, Reader, Xlsx};
+use ndarray::Array2;
+
+use crate::{synthesize_headers, ParseOptions, PreambleMatcher, SheetError, SpreadSheet2D};
+
+impl SpreadSheet2D {
+    /// reads a worksheet of an Excel workbook, the one named `sheet` or the first one. The first
+    /// `line_offset` rows of the used range become the preamble with their cells joined by tabs, the next
+    /// row is the header and the rest are data rows. Numbers are written the way Rust prints an `f64`
+    /// (integral values without a decimal point), dates as ISO 8601 (`2024-03-01` or
+    /// `2024-03-01T13:45:00`), booleans as `true`/`false`, errors like `#DIV/0!` as shown by Excel and
+    /// empty cells as empty strings. The spreadsheet is written tab delimited.
+    pub fn from_xlsx(path:&Path,sheet:Option<&str>,line_offset:usize) -> Result<Self,SheetError> {
+        let opts = ParseOptions { line_offset, ..Default::default() };
+        Self::from_xlsx_with_options(path, sheet, &opts)
+    }
+
+    /// same as `from_xlsx`, applying `line_offset`, `preamble_pattern`, `has_header`, `comment_prefix` and
+    /// `trim` of `opts` to the rows of the worksheet as if each row were a line of its cells joined by tabs.
+    /// The options about splitting lines into fields don't apply to a workbook and are ignored.
+    pub fn from_xlsx_with_options(path:&Path,sheet:Option<&str>,opts:&ParseOptions) -> Result<Self,SheetError> {
+        let matcher = PreambleMatcher::new(opts)?;
+        let mut workbook:Xlsx<_> = open_workbook(path)?;
+        let name = match sheet {
+            Some(name) => name.to_string(),
+            None => match workbook.sheet_names().first() {
+                Some(name) => name.clone(),
+                None => Err(format!("workbook {} has no worksheets",path.display()))?
+            }
+        };
+        if !workbook.sheet_names().contains(&name) {
+            Err(format!("workbook {} has no worksheet '{}'. Available worksheets: {}",path.display(),name,workbook.sheet_names().join(", ")))?
+        }
+        let range = workbook.worksheet_range(&name)?;
+
+        let mut rows = range.rows().map(|row|{
+            row.iter().map(|cell|{
+                let cell = cell_to_string(cell);
+                if opts.trim { cell.trim().to_string() } else { cell }
+            }).collect::<Vec<String>>()
+        }).peekable();
+        let mut preamble = vec![];
+        while let Some(row) = rows.next_if(|row| matcher.matches(preamble.len(), &row.join("\t"))) {
+            preamble.push(row.join("\t"));
+        }
+        let is_comment = |row:&[String]| opts.comment_prefix.as_ref().is_some_and(|prefix| row.join("\t").starts_with(prefix.as_str()));
+
+        let column_headers = if opts.has_header {
+            match rows.next() {
+                Some(header) => header,
+                None => Err(format!("worksheet '{}' ends before the header row after {} preamble row(s)",name,preamble.len()))?
+            }
+        }else {
+            match rows.peek() {
+                Some(row) => synthesize_headers(row.len()),
+                None => Err(format!("worksheet '{}' ends before the first data row after {} preamble row(s)",name,preamble.len()))?
+            }
+        };
+        let n_columns = column_headers.len();
+        let data_rows:Vec<Vec<String>> = rows.filter(|row| !is_comment(row)).collect();
+        let n_rows = data_rows.len();
+        let data = Array2::from_shape_vec((n_rows, n_columns), data_rows.into_iter().flatten().collect())?;
+        let mut sheet = Self::from_parts(preamble, "\t", column_headers, data);
+        sheet.set_write_header(opts.has_header);
+        Ok(sheet)
+    }
+}
+
+fn cell_to_string(cell:&Data) -> String {
+    match cell {
+        Data::DateTime(datetime) => datetime_to_string(datetime),
+        other => other.to_string(),
+    }
+}
+
+fn datetime_to_string(datetime:&ExcelDateTime) -> String {
+    // durations are kept as the number of days Excel stores
+    if datetime.is_duration() {
+        return datetime.as_f64().to_string()
+    }
+    let (year,month,day,hour,minute,second,milli) = datetime.to_ymd_hms_milli();
+    let date = format!("{:04}-{:02}-{:02}",year,month,day);
+    match (hour,minute,second,milli) {
+        (0,0,0,0) => date,
+        (_,_,_,0) => format!("{}T{:02}:{:02}:{:02}",date,hour,minute,second),
+        _ => format!("{}T{:02}:{:02}:{:02}.{:03}",date,hour,minute,second,milli),
+    }
+}